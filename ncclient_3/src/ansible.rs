@@ -0,0 +1,517 @@
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Host settings and the Ansible variables they are read from, in order of preference.
+/// The first variable is the one written on export.
+pub const VARIABLES: [(&str, &[&str]); 11] = [
+    ("port", &["ansible_port", "ansible_ssh_port"]),
+    ("user", &["ansible_user", "ansible_ssh_user"]),
+    ("password", &["ansible_password", "ansible_ssh_pass"]),
+    ("private_key_path", &["ansible_ssh_private_key_file", "ansible_private_key_file"]),
+    ("passphrase", &["netconf_passphrase"]),
+    ("use_agent", &["netconf_use_agent"]),
+    ("credential_profile", &["netconf_credential_profile"]),
+    ("poll_interval", &["netconf_poll_interval"]),
+    ("connect_timeout", &["netconf_connect_timeout"]),
+    ("handshake_timeout", &["netconf_handshake_timeout"]),
+    ("rpc_timeout", &["netconf_rpc_timeout"]),
+];
+
+/// Prefix of the variables holding the tags of a host, followed by the tag key.
+pub const TAG_PREFIX: &str = "netconf_tag_";
+
+/// A host of an Ansible inventory, with the variables of its groups and its own.
+#[derive(Debug, Clone)]
+pub struct InventoryHost {
+    /// The name of the host in the inventory.
+    pub name: String,
+    /// The variables of the host, with the host variables overriding those of the groups.
+    pub vars: BTreeMap<String, String>,
+    /// The groups of the host and their ancestors, except `all` and `ungrouped`, sorted by name.
+    pub groups: Vec<String>,
+}
+
+impl InventoryHost {
+    /// Returns the host settings found in the variables, keyed by the names of `VARIABLES`.
+    ///
+    /// The `host` field is `ansible_host`, or the name of the host in the inventory. The
+    /// `groups` field lists the groups separated by `;`, and every `netconf_tag_<key>`
+    /// variable gives a `tag.<key>` field.
+    pub fn fields(&self) -> BTreeMap<String, String> {
+        let mut fields = BTreeMap::new();
+        let host = self.vars.get("ansible_host").unwrap_or(&self.name);
+        fields.insert("host".to_string(), host.clone());
+        for (field, names) in VARIABLES {
+            if let Some(value) = names.iter().find_map(|name| self.vars.get(*name)) {
+                fields.insert(field.to_string(), value.clone());
+            }
+        }
+        fields.insert("groups".to_string(), self.groups.join(";"));
+        for (name, value) in &self.vars {
+            if let Some(key) = name.strip_prefix(TAG_PREFIX) {
+                fields.insert(format!("tag.{}", key), value.clone());
+            }
+        }
+        fields
+    }
+}
+
+/// A group of an Ansible inventory.
+#[derive(Debug, Default)]
+struct Group {
+    /// The variables of the group.
+    vars: BTreeMap<String, String>,
+    /// The names of the child groups.
+    children: Vec<String>,
+    /// The names of the hosts directly in the group.
+    hosts: Vec<String>,
+}
+
+/// An Ansible inventory, before the variables are resolved.
+#[derive(Debug, Default)]
+struct Inventory {
+    /// The groups per name.
+    groups: HashMap<String, Group>,
+    /// The host names, in the order they first appear.
+    hosts: Vec<String>,
+    /// The host variables per host name.
+    host_vars: HashMap<String, BTreeMap<String, String>>,
+}
+
+impl Inventory {
+    /// Returns a group, creating it if needed.
+    fn group(&mut self, name: &str) -> &mut Group {
+        self.groups.entry(name.to_string()).or_default()
+    }
+
+    /// Adds a host to a group, merging its variables with those already read.
+    fn add_host(&mut self, group: &str, host: String, vars: BTreeMap<String, String>) {
+        if !self.host_vars.contains_key(&host) {
+            self.hosts.push(host.clone());
+        }
+        self.host_vars.entry(host.clone()).or_default().extend(vars);
+        let group = self.group(group);
+        if !group.hosts.contains(&host) {
+            group.hosts.push(host);
+        }
+    }
+
+    /// Returns the parent groups of every group.
+    fn parents(&self) -> HashMap<&str, Vec<&str>> {
+        let mut parents: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (name, group) in &self.groups {
+            for child in &group.children {
+                parents.entry(child.as_str()).or_default().push(name.as_str());
+            }
+        }
+        parents
+    }
+
+    /// Resolves the variables of the hosts.
+    ///
+    /// Like Ansible, the variables of `all` come first, then those of the groups from the
+    /// least to the most nested, then the host variables.
+    ///
+    /// # Arguments
+    ///
+    /// * `only` - The group whose hosts, including those of its descendants, are returned.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the hosts in the order they appear, or an error message if
+    /// the group does not exist.
+    fn resolve(self, only: Option<&str>) -> Result<Vec<InventoryHost>, String> {
+        if let Some(only) = only {
+            if only != "all" && !self.groups.contains_key(only) {
+                return Err(format!("unknown group {}", only));
+            }
+        }
+        let parents = self.parents();
+        let mut resolved = vec![];
+        for host in &self.hosts {
+            // Collect the groups of the host and their ancestors.
+            let mut groups: HashSet<&str> = HashSet::from(["all"]);
+            let mut pending: Vec<&str> = self
+                .groups
+                .iter()
+                .filter(|(_, group)| group.hosts.contains(host))
+                .map(|(name, _)| name.as_str())
+                .collect();
+            while let Some(group) = pending.pop() {
+                if groups.insert(group) {
+                    pending.extend(parents.get(group).into_iter().flatten());
+                }
+            }
+            if only.is_some_and(|only| !groups.contains(only)) {
+                continue;
+            }
+
+            let mut ordered: Vec<(usize, &str)> = groups
+                .into_iter()
+                .map(|group| (depth(group, &parents, &mut HashSet::new()), group))
+                .collect();
+            ordered.sort();
+            let mut vars = BTreeMap::new();
+            for (_, group) in &ordered {
+                if let Some(group) = self.groups.get(*group) {
+                    vars.extend(group.vars.clone());
+                }
+            }
+            vars.extend(self.host_vars[host].clone());
+            let mut groups: Vec<String> = ordered
+                .iter()
+                .map(|(_, group)| group.to_string())
+                .filter(|group| group != "all" && group != "ungrouped")
+                .collect();
+            groups.sort();
+            resolved.push(InventoryHost { name: host.clone(), vars, groups });
+        }
+        Ok(resolved)
+    }
+}
+
+/// Returns the length of the longest chain of parents from `all` to a group, ignoring cycles.
+fn depth<'a>(group: &'a str, parents: &HashMap<&'a str, Vec<&'a str>>, seen: &mut HashSet<&'a str>) -> usize {
+    if group == "all" || !seen.insert(group) {
+        return 0;
+    }
+    let depth = parents
+        .get(group)
+        .into_iter()
+        .flatten()
+        .map(|parent| depth(parent, parents, seen))
+        .max()
+        .unwrap_or(0);
+    seen.remove(group);
+    depth + 1
+}
+
+/// Parses an Ansible inventory in the YAML or INI format.
+///
+/// # Arguments
+///
+/// * `inventory` - The inventory, read as YAML if it is a mapping and as INI otherwise.
+/// * `group` - The group whose hosts are returned, every host if `None`.
+///
+/// # Returns
+///
+/// A `Result` containing the hosts with their variables, or an error message if the
+/// inventory is invalid or the group does not exist.
+pub fn parse(inventory: &str, group: Option<&str>) -> Result<Vec<InventoryHost>, String> {
+    let inventory = match serde_yaml::from_str::<Value>(inventory) {
+        Ok(Value::Mapping(groups)) => from_yaml(&groups)?,
+        _ => from_ini(inventory)?,
+    };
+    inventory.resolve(group)
+}
+
+/// Reads a YAML inventory, a mapping of the top-level groups.
+fn from_yaml(groups: &Mapping) -> Result<Inventory, String> {
+    let mut inventory = Inventory::default();
+    for (name, group) in groups {
+        let name = scalar(name).ok_or("group names must be strings")?;
+        read_yaml_group(&mut inventory, &name, group)?;
+    }
+    Ok(inventory)
+}
+
+/// Reads a group of a YAML inventory, with its `vars`, `hosts` and `children`.
+fn read_yaml_group(inventory: &mut Inventory, name: &str, group: &Value) -> Result<(), String> {
+    inventory.group(name);
+    let group = match group {
+        Value::Null => return Ok(()),
+        Value::Mapping(group) => group,
+        _ => return Err(format!("group {} must be a mapping", name)),
+    };
+    if let Some(vars) = group.get("vars") {
+        let vars = yaml_vars(vars).ok_or_else(|| format!("the vars of group {} must be a mapping", name))?;
+        inventory.group(name).vars.extend(vars);
+    }
+    match group.get("hosts") {
+        None | Some(Value::Null) => {}
+        Some(Value::Mapping(hosts)) => {
+            for (host, vars) in hosts {
+                let host = scalar(host).ok_or_else(|| format!("the hosts of group {} must be strings", name))?;
+                let vars = yaml_vars(vars).ok_or_else(|| format!("the vars of host {} must be a mapping", host))?;
+                inventory.add_host(name, host, vars);
+            }
+        }
+        Some(_) => return Err(format!("the hosts of group {} must be a mapping", name)),
+    }
+    match group.get("children") {
+        None | Some(Value::Null) => {}
+        Some(Value::Mapping(children)) => {
+            for (child, value) in children {
+                let child = scalar(child).ok_or_else(|| format!("the children of group {} must be strings", name))?;
+                inventory.group(name).children.push(child.clone());
+                read_yaml_group(inventory, &child, value)?;
+            }
+        }
+        Some(_) => return Err(format!("the children of group {} must be a mapping", name)),
+    }
+    Ok(())
+}
+
+/// Returns the scalar variables of a YAML mapping, or `None` if the value is not a mapping.
+/// Lists and mappings, which cannot hold host settings, are skipped.
+fn yaml_vars(vars: &Value) -> Option<BTreeMap<String, String>> {
+    match vars {
+        Value::Null => Some(BTreeMap::new()),
+        Value::Mapping(vars) => Some(
+            vars.iter()
+                .filter_map(|(name, value)| Some((scalar(name)?, scalar(value)?)))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// Returns a YAML string, number or boolean as a string.
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Kind of the section of an INI inventory.
+enum Section {
+    /// `[group]`, listing hosts with their variables.
+    Hosts,
+    /// `[group:vars]`, listing variables of the group.
+    Vars,
+    /// `[group:children]`, listing child groups.
+    Children,
+}
+
+/// Reads an INI inventory. Hosts before the first section are in the `ungrouped` group.
+fn from_ini(ini: &str) -> Result<Inventory, String> {
+    let mut inventory = Inventory::default();
+    let mut group = "ungrouped".to_string();
+    let mut section = Section::Hosts;
+    for (number, line) in ini.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        let error = |message: String| format!("line {}: {}", number + 1, message);
+
+        // Start a new section.
+        if let Some(header) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            let (name, kind) = header.split_once(':').unwrap_or((header, ""));
+            section = match kind {
+                "" => Section::Hosts,
+                "vars" => Section::Vars,
+                "children" => Section::Children,
+                _ => return Err(error(format!("unknown section kind {:?}", kind))),
+            };
+            group = name.trim().to_string();
+            inventory.group(&group);
+            continue;
+        }
+
+        match section {
+            Section::Hosts => {
+                let mut words = split_words(line).into_iter();
+                let host = words.next().ok_or_else(|| error("missing host".to_string()))?;
+                let mut vars = BTreeMap::new();
+                for word in words {
+                    let (name, value) = word
+                        .split_once('=')
+                        .ok_or_else(|| error(format!("expected name=value, got {:?}", word)))?;
+                    vars.insert(name.to_string(), value.to_string());
+                }
+                inventory.add_host(&group, host, vars);
+            }
+            Section::Vars => {
+                let (name, value) = line
+                    .split_once('=')
+                    .ok_or_else(|| error(format!("expected name=value, got {:?}", line)))?;
+                let value = split_words(value).join(" ");
+                inventory.group(&group).vars.insert(name.trim().to_string(), value);
+            }
+            Section::Children => {
+                let child = line.to_string();
+                inventory.group(&child);
+                inventory.group(&group).children.push(child);
+            }
+        }
+    }
+    Ok(inventory)
+}
+
+/// Splits a line of an INI inventory on whitespace, removing quotes and a trailing comment.
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_word = true;
+            }
+            None if c == '#' && !in_word => break,
+            None if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            None => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Formats hosts as an Ansible YAML inventory.
+///
+/// The variables of every host are in the `all` group, and the hosts are also listed in
+/// children of `all` named after their groups.
+///
+/// # Arguments
+///
+/// * `hosts` - The hosts with their settings, keyed by the names of `VARIABLES`, `groups`
+///   (a list) and `tag.<key>`. Null and false settings are left out.
+///
+/// # Returns
+///
+/// A `Result` containing the YAML inventory, or an error if it cannot be serialized.
+pub fn to_yaml(hosts: &[(String, Vec<(String, serde_json::Value)>)]) -> Result<String, serde_yaml::Error> {
+    let mut inventory_hosts = Mapping::new();
+    let mut children: BTreeMap<String, Mapping> = BTreeMap::new();
+    for (host, fields) in hosts {
+        let mut vars = Mapping::new();
+        for (field, value) in fields {
+            if value.is_null() || value == &serde_json::Value::Bool(false) {
+                continue;
+            }
+            if field == "groups" {
+                for group in value.as_array().into_iter().flatten().filter_map(|group| group.as_str()) {
+                    let group_hosts = children.entry(group.to_string()).or_default();
+                    group_hosts.insert(Value::from(host.as_str()), Value::Null);
+                }
+            } else if let Some(key) = field.strip_prefix("tag.") {
+                vars.insert(Value::from(format!("{}{}", TAG_PREFIX, key)), serde_yaml::to_value(value)?);
+            } else if let Some((_, names)) = VARIABLES.iter().find(|(name, _)| name == field) {
+                vars.insert(Value::from(names[0]), serde_yaml::to_value(value)?);
+            }
+        }
+        inventory_hosts.insert(Value::from(host.as_str()), Value::Mapping(vars));
+    }
+    let mut all = Mapping::new();
+    all.insert(Value::from("hosts"), Value::Mapping(inventory_hosts));
+    if !children.is_empty() {
+        let children = children
+            .into_iter()
+            .map(|(group, group_hosts)| {
+                let mut group_content = Mapping::new();
+                group_content.insert(Value::from("hosts"), Value::Mapping(group_hosts));
+                (Value::from(group), Value::Mapping(group_content))
+            })
+            .collect();
+        all.insert(Value::from("children"), Value::Mapping(children));
+    }
+    let mut inventory = Mapping::new();
+    inventory.insert(Value::from("all"), Value::Mapping(all));
+    serde_yaml::to_string(&inventory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVENTORY: &str = "
+all:
+  vars:
+    ansible_user: admin
+    netconf_poll_interval: 60
+  children:
+    dc1:
+      vars:
+        ansible_user: ops
+        netconf_tag_site: dc1
+      children:
+        spine:
+          vars:
+            ansible_user: netops
+          hosts:
+            s1:
+              ansible_host: 192.0.2.1
+              ansible_port: 2830
+            s2:
+              ansible_user: root
+    lab:
+      hosts:
+        l1:
+";
+
+    fn host<'a>(hosts: &'a [InventoryHost], name: &str) -> &'a InventoryHost {
+        hosts.iter().find(|host| host.name == name).unwrap()
+    }
+
+    #[test]
+    fn nested_group_vars_override_their_parents() {
+        let hosts = parse(INVENTORY, None).unwrap();
+        assert_eq!(host(&hosts, "s1").vars["ansible_user"], "netops");
+        assert_eq!(host(&hosts, "s1").vars["netconf_tag_site"], "dc1");
+        assert_eq!(host(&hosts, "s1").vars["netconf_poll_interval"], "60");
+        assert_eq!(host(&hosts, "s2").vars["ansible_user"], "root");
+        assert_eq!(host(&hosts, "l1").vars["ansible_user"], "admin");
+        assert!(!host(&hosts, "l1").vars.contains_key("netconf_tag_site"));
+    }
+
+    #[test]
+    fn lists_the_groups_and_their_ancestors() {
+        let hosts = parse(INVENTORY, None).unwrap();
+        assert_eq!(host(&hosts, "s1").groups, ["dc1", "spine"]);
+        assert_eq!(host(&hosts, "l1").groups, ["lab"]);
+
+        let ini = "r0\n[spine]\nr1 ansible_user=admin\n[dc1:children]\nspine\n[dc1:vars]\nansible_user=ops\n";
+        let hosts = parse(ini, None).unwrap();
+        assert!(host(&hosts, "r0").groups.is_empty());
+        assert_eq!(host(&hosts, "r1").groups, ["dc1", "spine"]);
+        assert_eq!(host(&hosts, "r1").vars["ansible_user"], "admin");
+    }
+
+    #[test]
+    fn maps_the_ansible_variables_to_fields() {
+        let hosts = parse(INVENTORY, None).unwrap();
+        let fields = host(&hosts, "s1").fields();
+        assert_eq!(fields["host"], "192.0.2.1");
+        assert_eq!(fields["port"], "2830");
+        assert_eq!(fields["user"], "netops");
+        assert_eq!(fields["poll_interval"], "60");
+        assert_eq!(fields["groups"], "dc1;spine");
+        assert_eq!(fields["tag.site"], "dc1");
+        assert_eq!(host(&hosts, "s2").fields()["host"], "s2");
+        assert!(!host(&hosts, "s2").fields().contains_key("port"));
+
+        let hosts = parse("r1 ansible_ssh_port=22 ansible_ssh_user='net ops'\n", None).unwrap();
+        let fields = host(&hosts, "r1").fields();
+        assert_eq!(fields["port"], "22");
+        assert_eq!(fields["user"], "net ops");
+    }
+
+    #[test]
+    fn filters_the_hosts_of_a_group() {
+        let names = |group| -> Vec<String> {
+            parse(INVENTORY, group).unwrap().into_iter().map(|host| host.name).collect()
+        };
+        assert_eq!(names(Some("dc1")), ["s1", "s2"]);
+        assert_eq!(names(Some("lab")), ["l1"]);
+        assert_eq!(names(Some("all")).len(), 3);
+        assert!(parse(INVENTORY, Some("missing")).is_err());
+    }
+}
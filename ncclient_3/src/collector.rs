@@ -0,0 +1,361 @@
+use actix_web::web;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::errors::ApiError;
+use crate::session::SessionPool;
+use crate::tags::{self, with_tags};
+use crate::thresholds;
+use crate::utils::to_json;
+use crate::{AppState, HostParameters};
+
+/// NETCONF operation retrieving the openconfig transceiver data and temperature of every component.
+const TRANSCEIVER_OPERATION: &str = r#"
+<get>
+  <filter type="subtree">
+    <components xmlns="http://openconfig.net/yang/platform">
+      <component>
+        <state>
+          <temperature/>
+        </state>
+        <transceiver xmlns="http://openconfig.net/yang/platform/transceiver"/>
+      </component>
+    </components>
+  </filter>
+</get>
+"#;
+
+/// Outcome of the last collections of a host.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct HostStatus {
+    /// Unix timestamp of the last successful collection.
+    pub last_success: Option<f64>,
+    /// Unix timestamp of the last failed collection.
+    pub last_error: Option<f64>,
+    /// Description of the last failure.
+    pub last_error_message: Option<String>,
+}
+
+/// A transceiver found in the last successful collection of a host.
+#[derive(Debug, Clone, Serialize)]
+pub struct InventoryItem {
+    /// The hostname or IP address of the device.
+    pub host: String,
+    /// The name of the transceiver component.
+    pub component: String,
+    /// The serial number of the transceiver.
+    pub serial_no: Option<String>,
+    /// The vendor of the transceiver.
+    pub vendor: Option<String>,
+    /// The part number given by the vendor.
+    pub vendor_part: Option<String>,
+    /// The hardware revision given by the vendor.
+    pub vendor_rev: Option<String>,
+    /// Unix timestamp of the collection.
+    pub last_seen: f64,
+}
+
+/// Returns the current time as a Unix timestamp in seconds.
+pub fn unix_timestamp() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or_default()
+}
+
+/// Converts a JSON `Value` to an `Option<f64>`.
+/// 
+/// # Arguments
+/// 
+/// * `value` - A reference to the JSON `Value`.
+/// 
+/// # Returns
+/// 
+/// An `Option<f64>` which is `Some` if the value can be parsed as a `f64`, and `None` otherwise.
+pub fn value_to_f64(value: &Value) -> Option<f64> {
+    if let Value::String(s) = value {
+        s.parse::<f64>().map_err(|e| println!("{}", e)).ok()
+    } else {
+        None
+    }
+}
+
+/// Converts a JSON `Value` to an `Option<i64>`.
+/// 
+/// # Arguments
+/// 
+/// * `value` - A reference to the JSON `Value`.
+/// 
+/// # Returns
+/// 
+/// An `Option<i64>` which is `Some` if the value can be parsed as a `i64`, and `None` otherwise.
+pub fn value_to_i64(value: &Value) -> Option<i64> {
+    if let Value::String(s) = value {
+        s.parse::<i64>().map_err(|e| println!("{}", e)).ok()
+    } else {
+        None
+    }
+}
+
+/// Converts a JSON `Value` to a Prometheus label value.
+/// 
+/// Strings are used without their JSON quotes.
+pub fn value_to_label(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Returns the entries of a JSON value that may hold a single object or an array of objects.
+/// 
+/// XML lists with a single element are converted to an object instead of an array.
+pub fn as_list(value: Option<&Value>) -> Vec<&Value> {
+    match value {
+        Some(Value::Array(values)) => values.iter().collect(),
+        Some(value @ Value::Object(_)) => vec![value],
+        _ => vec![],
+    }
+}
+
+/// Removes the series of the metrics exported from the device replies that were last
+/// updated before the cutoff of their host.
+///
+/// # Arguments
+///
+/// * `state` - The application state holding the metrics.
+/// * `cutoff` - Returns the time before which the series of a host are removed, or `None`
+///   to keep them.
+pub fn expire_series(state: &AppState, cutoff: &dyn Fn(&str) -> Option<Instant>) {
+    for metric in state.metrics.iter() {
+        metric.expire(cutoff);
+    }
+    state.channel_status_metric.expire(cutoff);
+}
+
+/// Removes the series of a host last updated before a given time.
+pub fn expire_host_series(state: &AppState, host: &str, before: Instant) {
+    expire_series(state, &|series_host| (series_host == host).then_some(before));
+}
+
+/// Removes everything known about a host that is no longer registered: its status,
+/// inventory, channel status and metric series.
+pub fn forget_host(state: &AppState, host: &str) {
+    state.poll_status.lock().unwrap().remove(host);
+    state.inventory.lock().unwrap().remove(host);
+    state.channel_status.lock().unwrap().remove(host);
+    state.series_tags.lock().unwrap().remove(host);
+    expire_host_series(state, host, Instant::now());
+    state.poll_metrics.forget(host);
+}
+
+/// Returns the values of the tag labels of a host, and removes its health series if they
+/// were labelled with other tags. The other series are replaced by the next collection.
+///
+/// # Arguments
+///
+/// * `state` - The application state holding the metrics.
+/// * `host` - The hostname or IP address of the device.
+/// * `parameters` - The parameters of the host, holding its tags.
+pub fn series_tags(state: &AppState, host: &str, parameters: &HostParameters) -> Vec<String> {
+    let tags = tags::label_values(&state.tag_labels, parameters);
+    let former = state.series_tags.lock().unwrap().insert(host.to_string(), tags.clone());
+    if former.is_some_and(|former| former != tags) {
+        state.poll_metrics.forget(host);
+    }
+    tags
+}
+
+/// Collects the transceiver data of a host and updates the metrics.
+/// 
+/// The outcome is recorded in the host status and its health metrics. Every series is
+/// labelled with the tag labels of the host. Nothing is recorded if the host was removed
+/// from the dictionary during the collection.
+/// 
+/// # Arguments
+/// 
+/// * `host` - The hostname or IP address of the device.
+/// * `parameters` - The connection parameters of the host.
+/// * `host_dictionary` - The registered hosts.
+/// * `session_pool` - A `web::Data<SessionPool>` holding the persistent NETCONF sessions.
+/// * `state` - The application state holding the metrics.
+/// 
+/// # Returns
+/// 
+/// A `Result` containing the data of every component, or an `ApiError` if the host was
+/// removed, the device fails or times out, or its reply cannot be parsed.
+pub async fn collect(
+    host: &str,
+    parameters: HostParameters,
+    host_dictionary: &Mutex<HashMap<String, HostParameters>>,
+    session_pool: &web::Data<SessionPool>,
+    state: &AppState,
+) -> Result<Vec<HashMap<String, Value>>, ApiError> {
+    // Send the NETCONF request over a pooled session and convert the response to JSON.
+    // Both are blocking, so they run on the blocking thread pool instead of the worker.
+    let reply = {
+        let host = host.to_string();
+        let session_pool = session_pool.clone();
+        web::block(move || -> Result<Value, ApiError> {
+            let reply = session_pool.rpc(&host, &parameters, TRANSCEIVER_OPERATION)?;
+            Ok(to_json(&reply)?)
+        })
+        .await?
+    };
+
+    // Record the outcome while holding the dictionary, so that a host deleted during the
+    // collection is not recreated by it. `delete_host` removes everything else.
+    let host_dictionary = host_dictionary.lock().unwrap();
+    let tags = match host_dictionary.get(host) {
+        Some(parameters) => series_tags(state, host, parameters),
+        None => return Err(ApiError::UnknownHost(host.to_string())),
+    };
+    let result = reply.map(|json_response| update_transceivers(host, &json_response, &tags, state));
+
+    let now = unix_timestamp();
+    let mut poll_status = state.poll_status.lock().unwrap();
+    let status = poll_status.entry(host.to_string()).or_default();
+    let labels = with_tags(&[host], &tags);
+    match &result {
+        Ok(_) => {
+            status.last_success = Some(now);
+            state.poll_metrics.up.with_label_values(&labels).set(1.0);
+            state.poll_metrics.last_success.with_label_values(&labels).set(now);
+        }
+        Err(e) => {
+            status.last_error = Some(now);
+            status.last_error_message = Some(e.to_string());
+            state.poll_metrics.up.with_label_values(&labels).set(0.0);
+            state.poll_metrics.last_error.with_label_values(&labels).set(now);
+            state
+                .poll_metrics
+                .scrape_errors
+                .with_label_values(&with_tags(&[host, e.kind()], &tags))
+                .inc();
+        }
+    }
+    result
+}
+
+/// Extracts the transceiver data from a reply and updates the optical metrics, the
+/// inventory and the channel status of a host.
+fn update_transceivers(
+    host: &str,
+    json_response: &Value,
+    tags: &[String],
+    state: &AppState,
+) -> Vec<HashMap<String, Value>> {
+    // Initialize a list to store component data.
+    let mut components_list: Vec<HashMap<String, Value>> = vec![];
+
+    // Define JSON values for "PRESENT" and "NOT_PRESENT".
+    let present = json!("PRESENT");
+    let not_present = json!("NOT_PRESENT");
+
+    // Process the JSON response to extract component data.
+    for component in as_list(json_response.pointer("/rpc-reply/data/components/component")) {
+        // Components without a name cannot be labelled and are skipped.
+        let name = match component.get("name") {
+            Some(name) => name,
+            None => continue,
+        };
+        let mut json_component: HashMap<String, Value> = HashMap::new();
+        json_component.insert("name".to_string(), name.clone());
+
+        if component.pointer("/transceiver/state/present") == Some(&present) {
+            json_component.insert("present-state".to_string(), present.clone());
+            for leaf in [
+                "serial-no",
+                "vendor",
+                "vendor-part",
+                "vendor-rev",
+                "form-factor",
+                "connector-type",
+                "ethernet-pmd",
+                "fault-condition",
+                "supply-voltage",
+            ] {
+                if let Some(value) = component.pointer(&format!("/transceiver/state/{}", leaf)) {
+                    json_component.insert(leaf.to_string(), value.clone());
+                }
+            }
+            if let Some(temperature) = component.pointer("/state/temperature") {
+                json_component.insert("temperature".to_string(), temperature.clone());
+            }
+
+            let mut channels_list: Vec<Value> = vec![];
+            for channel in as_list(component.pointer("/transceiver/physical-channels/channel")) {
+                let mut json_channel: HashMap<String, Value> = HashMap::new();
+                if let Some(state) = channel.get("state").and_then(|s| s.as_object()) {
+                    for (k, v) in state {
+                        json_channel.insert(k.clone(), v.clone());
+                    }
+                };
+                // The index key of the list is also valid when the state omits it.
+                if !json_channel.contains_key("index") {
+                    if let Some(index) = channel.get("index") {
+                        json_channel.insert("index".to_string(), index.clone());
+                    }
+                }
+                channels_list.push(json!(json_channel));
+            }
+            if !channels_list.is_empty() {
+                json_component.insert("channel".to_string(), json!(channels_list));
+            }
+        } else {
+            json_component.insert("present-state".to_string(), not_present.clone());
+        }
+        components_list.push(json_component);
+    }
+
+    // Replace the inventory of the host with the transceivers present.
+    let now = unix_timestamp();
+    let inventory = components_list
+        .iter()
+        .filter(|c| c.get("present-state") == Some(&present))
+        .map(|c| {
+            let leaf = |name: &str| c.get(name).map(value_to_label);
+            InventoryItem {
+                host: host.to_string(),
+                component: leaf("name").unwrap_or_default(),
+                serial_no: leaf("serial-no"),
+                vendor: leaf("vendor"),
+                vendor_part: leaf("vendor-part"),
+                vendor_rev: leaf("vendor-rev"),
+                last_seen: now,
+            }
+        })
+        .collect();
+    state.inventory.lock().unwrap().insert(host.to_string(), inventory);
+
+    // Update the metrics declared in the definition file from the reply.
+    let updated = Instant::now();
+    for metric in state.metrics.iter() {
+        for (labels, value) in metric.definition.samples(json_response) {
+            let mut label_values = vec![host];
+            label_values.extend(labels.iter().map(|l| l.as_str()));
+            label_values.extend(tags.iter().map(|t| t.as_str()));
+            metric.set(&label_values, value);
+        }
+    }
+
+    // Compare the channels with the thresholds of their transceiver.
+    let statuses = thresholds::evaluate(host, json_response);
+    for status in &statuses {
+        state
+            .channel_status_metric
+            .set(
+                &with_tags(&[host, &status.component, &status.channel, status.quantity], tags),
+                status.status.value(),
+            );
+    }
+    state.channel_status.lock().unwrap().insert(host.to_string(), statuses);
+
+    // Remove the series of the host missing from the reply, such as components that were
+    // removed, renamed or are no longer present.
+    expire_host_series(state, host, updated);
+    components_list
+}
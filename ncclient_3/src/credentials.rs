@@ -0,0 +1,417 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::sync::Mutex;
+
+use crate::host_store::{ProfileStore, Store};
+use crate::{AuthMethod, HostParameters, PrivateKey};
+
+/// Prefix of the secrets encrypted in the stores, followed by the base64 encoded nonce and ciphertext.
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// Size in bytes of the AES-GCM nonce.
+const NONCE_SIZE: usize = 12;
+
+/// Size in bytes of the master key.
+const KEY_SIZE: usize = 32;
+
+/// Returns whether secrets may be stored in plain text when no master key is configured,
+/// from the `NETCONF_ALLOW_PLAINTEXT_SECRETS` environment variable.
+pub fn plain_text_allowed_from_env() -> bool {
+    env::var("NETCONF_ALLOW_PLAINTEXT_SECRETS").is_ok_and(|v| v == "true" || v == "1")
+}
+
+/// Encrypts and decrypts the secrets of the stores with the master key, using AES-256-GCM.
+#[derive(Clone)]
+pub struct Cipher {
+    /// The cipher keyed with the master key.
+    cipher: Aes256Gcm,
+}
+
+impl Cipher {
+    /// Creates a cipher from a 32 byte master key.
+    pub fn new(key: &[u8]) -> io::Result<Self> {
+        let cipher = Aes256Gcm::new_from_slice(key).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("the master key must be {} bytes", KEY_SIZE))
+        })?;
+        Ok(Cipher { cipher })
+    }
+
+    /// Reads the base64 encoded master key from the `NETCONF_MASTER_KEY` environment variable,
+    /// or from the file given by `NETCONF_MASTER_KEY_FILE`.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the cipher, `None` if no key is configured, or an error if
+    /// the key file cannot be read or the key is invalid.
+    pub fn from_env() -> io::Result<Option<Self>> {
+        let encoded = match (env::var("NETCONF_MASTER_KEY"), env::var("NETCONF_MASTER_KEY_FILE")) {
+            (Ok(key), _) => key,
+            (Err(_), Ok(path)) => fs::read_to_string(path)?,
+            (Err(_), Err(_)) => return Ok(None),
+        };
+        let key = STANDARD
+            .decode(encoded.trim())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid master key: {}", e)))?;
+        Cipher::new(&key).map(Some)
+    }
+
+    /// Encrypts a secret with a random nonce.
+    pub fn encrypt(&self, secret: &str) -> io::Result<String> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), secret.as_bytes())
+            .map_err(|_| io::Error::other("failed to encrypt a secret"))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(sealed)))
+    }
+
+    /// Decrypts a secret returned by `encrypt`.
+    pub fn decrypt(&self, value: &str) -> io::Result<String> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "cannot decrypt a secret, is the master key right?");
+        let sealed = value
+            .strip_prefix(ENCRYPTED_PREFIX)
+            .and_then(|encoded| STANDARD.decode(encoded).ok())
+            .filter(|sealed| sealed.len() > NONCE_SIZE)
+            .ok_or_else(invalid)?;
+        let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+        let secret = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| invalid())?;
+        String::from_utf8(secret).map_err(|_| invalid())
+    }
+}
+
+/// Records holding secrets, which are encrypted when they are stored.
+pub trait Secrets: Sized {
+    /// Returns a copy of the record with every secret converted by `convert`.
+    fn map_secrets(&self, convert: &mut dyn FnMut(&str) -> io::Result<String>) -> io::Result<Self>;
+}
+
+/// Converts the passwords, inline private keys and passphrases of authentication methods.
+fn map_methods(
+    methods: &[AuthMethod],
+    convert: &mut dyn FnMut(&str) -> io::Result<String>,
+) -> io::Result<Vec<AuthMethod>> {
+    methods
+        .iter()
+        .map(|method| {
+            Ok(match method {
+                AuthMethod::PrivateKey { key, passphrase } => AuthMethod::PrivateKey {
+                    key: match key {
+                        PrivateKey::Inline(pem) => PrivateKey::Inline(convert(pem)?),
+                        PrivateKey::File(path) => PrivateKey::File(path.clone()),
+                    },
+                    passphrase: passphrase.as_deref().map(&mut *convert).transpose()?,
+                },
+                AuthMethod::Agent => AuthMethod::Agent,
+                AuthMethod::Password(password) => AuthMethod::Password(convert(password)?),
+            })
+        })
+        .collect()
+}
+
+impl Secrets for HostParameters {
+    fn map_secrets(&self, convert: &mut dyn FnMut(&str) -> io::Result<String>) -> io::Result<Self> {
+        Ok(HostParameters {
+            auth_methods: map_methods(&self.auth_methods, convert)?,
+            ..self.clone()
+        })
+    }
+}
+
+/// Named credentials shared by many hosts.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CredentialProfile {
+    /// The username, replacing the one of the hosts if set.
+    #[serde(default)]
+    pub user: Option<String>,
+    /// The authentication methods, tried in order, replacing those of the hosts.
+    #[serde(with = "serde_yaml::with::singleton_map_recursive")]
+    pub auth_methods: Vec<AuthMethod>,
+}
+
+impl Secrets for CredentialProfile {
+    fn map_secrets(&self, convert: &mut dyn FnMut(&str) -> io::Result<String>) -> io::Result<Self> {
+        Ok(CredentialProfile {
+            user: self.user.clone(),
+            auth_methods: map_methods(&self.auth_methods, convert)?,
+        })
+    }
+}
+
+/// Store decorator encrypting the secrets of the records before they reach the backend.
+///
+/// Without a master key, records holding secrets are refused unless plain text is allowed.
+/// Plain text secrets found while loading are encrypted again if a master key is configured.
+pub struct SealedStore<T> {
+    /// The backend holding the encrypted records.
+    inner: Box<dyn Store<T>>,
+    /// The cipher keyed with the master key.
+    cipher: Option<Cipher>,
+    /// Whether secrets are stored in plain text without a master key.
+    allow_plain_text: bool,
+}
+
+impl<T> SealedStore<T> {
+    /// Wraps a backend.
+    pub fn new(inner: Box<dyn Store<T>>, cipher: Option<Cipher>, allow_plain_text: bool) -> Self {
+        SealedStore { inner, cipher, allow_plain_text }
+    }
+}
+
+impl<T: Secrets + Send + Sync> Store<T> for SealedStore<T> {
+    fn load(&self) -> io::Result<HashMap<String, T>> {
+        let mut records = HashMap::new();
+        let mut migrated = vec![];
+        for (name, record) in self.inner.load()? {
+            let mut plain_text = false;
+            let record = record.map_secrets(&mut |secret| match &self.cipher {
+                _ if !secret.starts_with(ENCRYPTED_PREFIX) => {
+                    plain_text = true;
+                    Ok(secret.to_string())
+                }
+                Some(cipher) => cipher.decrypt(secret),
+                None => Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} holds encrypted secrets, a master key is required", name),
+                )),
+            })?;
+            if plain_text && self.cipher.is_some() {
+                migrated.push((name.clone(), self.seal(&record)?));
+            }
+            records.insert(name, record);
+        }
+        // Encrypt the secrets stored before the master key was configured.
+        if !migrated.is_empty() {
+            self.inner.put_all(&migrated)?;
+        }
+        Ok(records)
+    }
+
+    fn put(&self, name: &str, value: &T) -> io::Result<()> {
+        self.inner.put(name, &self.seal(value)?)
+    }
+
+    fn put_all(&self, records: &[(String, T)]) -> io::Result<()> {
+        let sealed = records
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), self.seal(value)?)))
+            .collect::<io::Result<Vec<_>>>()?;
+        self.inner.put_all(&sealed)
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        self.inner.delete(name)
+    }
+}
+
+impl<T: Secrets> SealedStore<T> {
+    /// Returns a copy of a record with its secrets encrypted, or refused without master key.
+    fn seal(&self, value: &T) -> io::Result<T> {
+        match &self.cipher {
+            Some(cipher) => value.map_secrets(&mut |secret| cipher.encrypt(secret)),
+            None if self.allow_plain_text => value.map_secrets(&mut |secret| Ok(secret.to_string())),
+            None => value.map_secrets(&mut |_| {
+                Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "secrets are only stored with a master key, set NETCONF_MASTER_KEY or NETCONF_ALLOW_PLAINTEXT_SECRETS",
+                ))
+            }),
+        }
+    }
+}
+
+/// The credential profiles, persisted in a store.
+pub struct CredentialProfiles {
+    /// The store the profiles are written through to.
+    store: Box<ProfileStore>,
+    /// The profiles per name.
+    profiles: Mutex<HashMap<String, CredentialProfile>>,
+}
+
+impl CredentialProfiles {
+    /// Loads the profiles from a store.
+    pub fn load(store: Box<ProfileStore>) -> io::Result<Self> {
+        let profiles = store.load()?;
+        Ok(CredentialProfiles { store, profiles: Mutex::new(profiles) })
+    }
+
+    /// Returns every profile.
+    pub fn list(&self) -> HashMap<String, CredentialProfile> {
+        self.profiles.lock().unwrap().clone()
+    }
+
+    /// Returns a profile.
+    pub fn get(&self, name: &str) -> Option<CredentialProfile> {
+        self.profiles.lock().unwrap().get(name).cloned()
+    }
+
+    /// Adds or replaces a profile, writing it through to the store.
+    pub fn put(&self, name: &str, profile: CredentialProfile) -> io::Result<()> {
+        let mut profiles = self.profiles.lock().unwrap();
+        self.store.put(name, &profile)?;
+        profiles.insert(name.to_string(), profile);
+        Ok(())
+    }
+
+    /// Removes a profile from the store.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing whether the profile existed.
+    pub fn delete(&self, name: &str) -> io::Result<bool> {
+        let mut profiles = self.profiles.lock().unwrap();
+        if !profiles.contains_key(name) {
+            return Ok(false);
+        }
+        self.store.delete(name)?;
+        profiles.remove(name);
+        Ok(true)
+    }
+
+    /// Returns the parameters used to connect to a host, with the user and authentication
+    /// methods of its credential profile.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the parameters, or an error message if the profile does not exist.
+    pub fn resolve(&self, parameters: &HostParameters) -> Result<HostParameters, String> {
+        let name = match &parameters.credential_profile {
+            Some(name) => name,
+            None => return Ok(parameters.clone()),
+        };
+        let profile = self
+            .get(name)
+            .ok_or_else(|| format!("unknown credential profile {}", name))?;
+        let mut resolved = parameters.clone();
+        if let Some(user) = profile.user {
+            resolved.user = user;
+        }
+        resolved.auth_methods = profile.auth_methods;
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Backend keeping the records in memory, as the sealed store hands them over.
+    struct MemoryStore(Arc<Mutex<HashMap<String, CredentialProfile>>>);
+
+    impl Store<CredentialProfile> for MemoryStore {
+        fn load(&self) -> io::Result<HashMap<String, CredentialProfile>> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+
+        fn put(&self, name: &str, value: &CredentialProfile) -> io::Result<()> {
+            self.0.lock().unwrap().insert(name.to_string(), value.clone());
+            Ok(())
+        }
+
+        fn put_all(&self, records: &[(String, CredentialProfile)]) -> io::Result<()> {
+            self.0.lock().unwrap().extend(records.iter().cloned());
+            Ok(())
+        }
+
+        fn delete(&self, name: &str) -> io::Result<()> {
+            self.0.lock().unwrap().remove(name);
+            Ok(())
+        }
+    }
+
+    fn profile(password: &str) -> CredentialProfile {
+        CredentialProfile {
+            user: Some("admin".to_string()),
+            auth_methods: vec![AuthMethod::Password(password.to_string())],
+        }
+    }
+
+    fn stored_password(records: &Mutex<HashMap<String, CredentialProfile>>) -> String {
+        match &records.lock().unwrap()["lab"].auth_methods[0] {
+            AuthMethod::Password(password) => password.clone(),
+            method => panic!("unexpected method {:?}", method),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let cipher = Cipher::new(&[7; KEY_SIZE]).unwrap();
+        let sealed = cipher.encrypt("secret").unwrap();
+        assert!(sealed.starts_with(ENCRYPTED_PREFIX));
+        assert_ne!(sealed, cipher.encrypt("secret").unwrap(), "the nonce must be random");
+        assert_eq!(cipher.decrypt(&sealed).unwrap(), "secret");
+    }
+
+    #[test]
+    fn rejects_tampered_input() {
+        let cipher = Cipher::new(&[7; KEY_SIZE]).unwrap();
+        let sealed = cipher.encrypt("secret").unwrap();
+        let mut bytes = STANDARD.decode(&sealed[ENCRYPTED_PREFIX.len()..]).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        let tampered = format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(&bytes));
+        assert_eq!(cipher.decrypt(&tampered).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(cipher.decrypt("enc:v1:not base64").is_err());
+        assert!(cipher.decrypt("enc:v1:AAAA").is_err());
+        assert!(cipher.decrypt(&sealed[ENCRYPTED_PREFIX.len()..]).is_err());
+    }
+
+    #[test]
+    fn rejects_wrong_key() {
+        let sealed = Cipher::new(&[7; KEY_SIZE]).unwrap().encrypt("secret").unwrap();
+        let other = Cipher::new(&[8; KEY_SIZE]).unwrap();
+        assert_eq!(other.decrypt(&sealed).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(Cipher::new(&[7; 16]).is_err());
+    }
+
+    #[test]
+    fn sealed_store_encrypts_secrets() {
+        let records = Arc::new(Mutex::new(HashMap::new()));
+        let cipher = Cipher::new(&[7; KEY_SIZE]).unwrap();
+        let store = SealedStore::new(Box::new(MemoryStore(records.clone())), Some(cipher.clone()), false);
+        store.put("lab", &profile("secret")).unwrap();
+        assert!(stored_password(&records).starts_with(ENCRYPTED_PREFIX));
+        assert_eq!(store.load().unwrap()["lab"], profile("secret"));
+
+        // A store sealed with another key, or without key, cannot be loaded.
+        let other = Cipher::new(&[8; KEY_SIZE]).unwrap();
+        assert!(SealedStore::new(Box::new(MemoryStore(records.clone())), Some(other), false).load().is_err());
+        assert!(SealedStore::new(Box::new(MemoryStore(records)), None, true).load().is_err());
+    }
+
+    #[test]
+    fn sealed_store_encrypts_plain_text_secrets_on_load() {
+        let records = Arc::new(Mutex::new(HashMap::from([("lab".to_string(), profile("secret"))])));
+        let cipher = Cipher::new(&[7; KEY_SIZE]).unwrap();
+        let store = SealedStore::new(Box::new(MemoryStore(records.clone())), Some(cipher.clone()), false);
+        assert_eq!(store.load().unwrap()["lab"], profile("secret"));
+        assert_eq!(cipher.decrypt(&stored_password(&records)).unwrap(), "secret");
+    }
+
+    #[test]
+    fn sealed_store_refuses_plain_text_secrets() {
+        let records = Arc::new(Mutex::new(HashMap::new()));
+        let store = SealedStore::new(Box::new(MemoryStore(records.clone())), None, false);
+        assert_eq!(store.put("lab", &profile("secret")).unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        let agent = CredentialProfile { user: None, auth_methods: vec![AuthMethod::Agent] };
+        store.put("agent", &agent).unwrap();
+
+        let store = SealedStore::new(Box::new(MemoryStore(records.clone())), None, true);
+        store.put("lab", &profile("secret")).unwrap();
+        assert_eq!(stored_password(&records), "secret");
+    }
+}
@@ -0,0 +1,119 @@
+use actix_web::error::BlockingError;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde_json::json;
+use std::error::Error;
+use std::fmt;
+
+use crate::netconf::{RpcErrors, TimeoutError, UnsupportedCapability};
+use crate::utils;
+
+/// Errors returned by the HTTP API, each mapped to a status code and a JSON body.
+#[derive(Debug)]
+pub enum ApiError {
+    /// The host is not registered (`404`).
+    UnknownHost(String),
+    /// A resource other than a host was not found (`404`).
+    NotFound(String),
+    /// The request is invalid (`400`).
+    BadRequest(String),
+    /// The resource already exists (`409`).
+    Conflict(String),
+    /// The device could not be reached or returned an error (`502`).
+    Device(String),
+    /// The device answered the RPC with `<rpc-error>` elements (`502`).
+    Rpc(RpcErrors),
+    /// The device did not answer in time (`504`).
+    Timeout(String),
+    /// The reply of the device could not be parsed (`422`).
+    Parse(String),
+    /// The service failed to process the request (`500`).
+    Internal(String),
+}
+
+impl ApiError {
+    /// Returns the machine readable kind of the error, used in the JSON body and as the
+    /// `reason` label of `netconf_scrape_errors_total`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ApiError::UnknownHost(_) => "unknown_host",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Device(_) => "device_error",
+            ApiError::Rpc(_) => "rpc_error",
+            ApiError::Timeout(_) => "timeout",
+            ApiError::Parse(_) => "parse_error",
+            ApiError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::UnknownHost(host) => write!(f, "unknown host {}", host),
+            ApiError::Rpc(errors) => write!(f, "{}", errors),
+            ApiError::NotFound(message)
+            | ApiError::BadRequest(message)
+            | ApiError::Conflict(message)
+            | ApiError::Device(message)
+            | ApiError::Timeout(message)
+            | ApiError::Parse(message)
+            | ApiError::Internal(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Error for ApiError {}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::UnknownHost(_) | ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Device(_) | ApiError::Rpc(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Parse(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut body = json!({
+            "error": self.kind(),
+            "message": self.to_string(),
+        });
+        // Pass the parsed rpc-error elements to the caller.
+        if let ApiError::Rpc(errors) = self {
+            body["rpc-errors"] = json!(errors.errors);
+        }
+        HttpResponse::build(self.status_code()).json(body)
+    }
+}
+
+/// Errors returned by the NETCONF layer are device errors, unless caused by a timeout,
+/// by an `<rpc-error>` reply, or by a request the device does not support.
+impl From<Box<dyn Error>> for ApiError {
+    fn from(error: Box<dyn Error>) -> Self {
+        match error.downcast::<RpcErrors>() {
+            Ok(errors) => ApiError::Rpc(*errors),
+            Err(error) if error.is::<TimeoutError>() => ApiError::Timeout(error.to_string()),
+            Err(error) if error.is::<UnsupportedCapability>() => ApiError::BadRequest(error.to_string()),
+            Err(error) => ApiError::Device(error.to_string()),
+        }
+    }
+}
+
+impl From<utils::Error> for ApiError {
+    fn from(error: utils::Error) -> Self {
+        ApiError::Parse(error.to_string())
+    }
+}
+
+impl From<BlockingError> for ApiError {
+    fn from(error: BlockingError) -> Self {
+        ApiError::Internal(error.to_string())
+    }
+}
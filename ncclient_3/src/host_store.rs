@@ -0,0 +1,356 @@
+use rusqlite::{params, Connection};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::credentials::{plain_text_allowed_from_env, Cipher, CredentialProfile, SealedStore};
+use crate::HostParameters;
+
+/// Default location of the host inventory, overridden by `NETCONF_HOST_STORE`.
+const DEFAULT_HOST_STORE: &str = "hosts.json";
+
+/// Default location of the credential profiles, overridden by `NETCONF_PROFILE_STORE`.
+const DEFAULT_PROFILE_STORE: &str = "credential_profiles.json";
+
+/// Persistent storage of named records, such as the registered hosts.
+///
+/// The store is loaded at startup, and every change is written through to it before
+/// being applied in memory.
+pub trait Store<T>: Send + Sync {
+    /// Returns every stored record.
+    fn load(&self) -> io::Result<HashMap<String, T>>;
+
+    /// Adds a record, or replaces it.
+    fn put(&self, name: &str, value: &T) -> io::Result<()>;
+
+    /// Adds or replaces many records in a single write, storing either all or none of them.
+    fn put_all(&self, records: &[(String, T)]) -> io::Result<()>;
+
+    /// Removes a record, ignoring unknown names.
+    fn delete(&self, name: &str) -> io::Result<()>;
+}
+
+/// Store of the registered hosts.
+pub type HostStore = dyn Store<HostParameters>;
+
+/// Store of the credential profiles.
+pub type ProfileStore = dyn Store<CredentialProfile>;
+
+/// Opens the host store given by the `NETCONF_HOST_STORE` environment variable.
+///
+/// Secrets are encrypted with the master key, if one is configured.
+pub fn hosts_from_env(cipher: Option<Cipher>) -> io::Result<Box<HostStore>> {
+    let path = env::var("NETCONF_HOST_STORE").unwrap_or_else(|_| DEFAULT_HOST_STORE.to_string());
+    let backend = open(Path::new(&path), ("hosts", "host", "parameters"))?;
+    Ok(Box::new(SealedStore::new(backend, cipher, plain_text_allowed_from_env())))
+}
+
+/// Opens the credential profile store given by the `NETCONF_PROFILE_STORE` environment variable.
+///
+/// Secrets are encrypted with the master key, if one is configured.
+pub fn profiles_from_env(cipher: Option<Cipher>) -> io::Result<Box<ProfileStore>> {
+    let path = env::var("NETCONF_PROFILE_STORE").unwrap_or_else(|_| DEFAULT_PROFILE_STORE.to_string());
+    let backend = open(Path::new(&path), ("profiles", "name", "profile"))?;
+    Ok(Box::new(SealedStore::new(backend, cipher, plain_text_allowed_from_env())))
+}
+
+/// Opens a store.
+///
+/// The backend is chosen from the extension of the path: `.db`, `.sqlite` and `.sqlite3`
+/// use SQLite, `.yaml` and `.yml` a YAML file, and any other extension a JSON file.
+///
+/// # Arguments
+///
+/// * `path` - The file or database holding the records.
+/// * `table` - The SQLite table holding the records, with its name and value columns.
+///
+/// # Returns
+///
+/// An `io::Result` containing the store, or an error if the database cannot be opened.
+fn open<T>(path: &Path, table: Table) -> io::Result<Box<dyn Store<T>>>
+where
+    T: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+    Ok(match extension {
+        "db" | "sqlite" | "sqlite3" => Box::new(SqliteStore::open(path, table)?),
+        "yaml" | "yml" => Box::new(FileStore::new(path.to_path_buf(), FileFormat::Yaml)),
+        _ => Box::new(FileStore::new(path.to_path_buf(), FileFormat::Json)),
+    })
+}
+
+/// A SQLite table name, followed by the names of its name and value columns.
+type Table = (&'static str, &'static str, &'static str);
+
+/// Serialization format of a `FileStore`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    /// A JSON object keyed by name.
+    Json,
+    /// A YAML mapping keyed by name.
+    Yaml,
+}
+
+/// Store keeping every record in a single JSON or YAML file, rewritten on every change.
+pub struct FileStore<T> {
+    /// The file holding the records.
+    path: PathBuf,
+    /// The serialization format of the file.
+    format: FileFormat,
+    /// The content of the file, serializing the writes.
+    records: Mutex<HashMap<String, T>>,
+}
+
+impl<T> FileStore<T> {
+    /// Creates a store backed by a file, which is created on the first change.
+    pub fn new(path: PathBuf, format: FileFormat) -> Self {
+        FileStore { path, format, records: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl<T: Serialize> FileStore<T> {
+    /// Writes the records to a temporary file renamed over the store, so a failed write
+    /// never leaves a truncated file.
+    fn write(&self, records: &HashMap<String, T>) -> io::Result<()> {
+        let content = match self.format {
+            FileFormat::Json => serde_json::to_string_pretty(records)?,
+            FileFormat::Yaml => {
+                serde_yaml::to_string(records).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            }
+        };
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, content)?;
+        fs::rename(&temporary, &self.path)
+    }
+}
+
+impl<T> Store<T> for FileStore<T>
+where
+    T: Serialize + DeserializeOwned + Clone + Send + Sync,
+{
+    fn load(&self) -> io::Result<HashMap<String, T>> {
+        let records: HashMap<String, T> = match fs::read_to_string(&self.path) {
+            Ok(content) => match self.format {
+                FileFormat::Json => serde_json::from_str(&content)?,
+                FileFormat::Yaml => {
+                    serde_yaml::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        *self.records.lock().unwrap() = records.clone();
+        Ok(records)
+    }
+
+    fn put(&self, name: &str, value: &T) -> io::Result<()> {
+        let mut records = self.records.lock().unwrap();
+        let mut updated = records.clone();
+        updated.insert(name.to_string(), value.clone());
+        self.write(&updated)?;
+        *records = updated;
+        Ok(())
+    }
+
+    fn put_all(&self, changes: &[(String, T)]) -> io::Result<()> {
+        let mut records = self.records.lock().unwrap();
+        let mut updated = records.clone();
+        updated.extend(changes.iter().cloned());
+        self.write(&updated)?;
+        *records = updated;
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        let mut records = self.records.lock().unwrap();
+        if !records.contains_key(name) {
+            return Ok(());
+        }
+        let mut updated = records.clone();
+        updated.remove(name);
+        self.write(&updated)?;
+        *records = updated;
+        Ok(())
+    }
+}
+
+/// Store keeping every record as JSON in a row of a table of an embedded SQLite database.
+pub struct SqliteStore<T> {
+    /// The connection to the database.
+    connection: Mutex<Connection>,
+    /// The table holding the records, with its name and value columns.
+    table: Table,
+    /// The type of the records.
+    records: PhantomData<fn() -> T>,
+}
+
+impl<T> SqliteStore<T> {
+    /// Opens or creates the database and the table of the records.
+    pub fn open(path: &Path, table: Table) -> io::Result<Self> {
+        let connection = Connection::open(path).map_err(sqlite_error)?;
+        let (table_name, name, value) = table;
+        connection
+            .execute(
+                &format!("CREATE TABLE IF NOT EXISTS {} ({} TEXT PRIMARY KEY, {} TEXT NOT NULL)", table_name, name, value),
+                [],
+            )
+            .map_err(sqlite_error)?;
+        Ok(SqliteStore { connection: Mutex::new(connection), table, records: PhantomData })
+    }
+
+    /// Returns the statement adding a record, or replacing it.
+    fn upsert(&self) -> String {
+        let (table, name, value) = self.table;
+        format!(
+            "INSERT INTO {0} ({1}, {2}) VALUES (?1, ?2)
+             ON CONFLICT ({1}) DO UPDATE SET {2} = excluded.{2}",
+            table, name, value
+        )
+    }
+}
+
+impl<T> Store<T> for SqliteStore<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync,
+{
+    fn load(&self) -> io::Result<HashMap<String, T>> {
+        let (table, name, value) = self.table;
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection
+            .prepare(&format!("SELECT {}, {} FROM {}", name, value, table))
+            .map_err(sqlite_error)?;
+        let rows = statement
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .map_err(sqlite_error)?;
+        let mut records = HashMap::new();
+        for row in rows {
+            let (name, value) = row.map_err(sqlite_error)?;
+            records.insert(name, serde_json::from_str(&value)?);
+        }
+        Ok(records)
+    }
+
+    fn put(&self, name: &str, value: &T) -> io::Result<()> {
+        let value = serde_json::to_string(value)?;
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(&self.upsert(), params![name, value])
+            .map_err(sqlite_error)?;
+        Ok(())
+    }
+
+    fn put_all(&self, records: &[(String, T)]) -> io::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction().map_err(sqlite_error)?;
+        for (name, value) in records {
+            let value = serde_json::to_string(value)?;
+            transaction.execute(&self.upsert(), params![name, value]).map_err(sqlite_error)?;
+        }
+        transaction.commit().map_err(sqlite_error)
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        self.connection
+            .lock()
+            .unwrap()
+            .execute(&format!("DELETE FROM {} WHERE {} = ?1", self.table.0, self.table.1), params![name])
+            .map_err(sqlite_error)?;
+        Ok(())
+    }
+}
+
+/// Converts a SQLite error to an `io::Error`.
+fn sqlite_error(error: rusqlite::Error) -> io::Error {
+    io::Error::other(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an empty directory for the files of a test.
+    fn directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("host_store_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn records(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    /// Puts, replaces and deletes records, checking them in a store reopened after every change.
+    fn round_trip(open: &dyn Fn() -> Box<dyn Store<String>>) {
+        let store = open();
+        assert!(store.load().unwrap().is_empty());
+        store.put("r1", &"a".to_string()).unwrap();
+        store.put("r2", &"b".to_string()).unwrap();
+        assert_eq!(open().load().unwrap(), records(&[("r1", "a"), ("r2", "b")]));
+
+        store.put("r1", &"c".to_string()).unwrap();
+        store.delete("r2").unwrap();
+        store.delete("unknown").unwrap();
+        assert_eq!(open().load().unwrap(), records(&[("r1", "c")]));
+
+        store.put_all(&[("r1".to_string(), "d".to_string()), ("r3".to_string(), "e".to_string())]).unwrap();
+        assert_eq!(open().load().unwrap(), records(&[("r1", "d"), ("r3", "e")]));
+    }
+
+    /// Opens a file store and loads it, as at startup.
+    fn file_store(path: &Path, format: FileFormat) -> Box<dyn Store<String>> {
+        let store = FileStore::new(path.to_path_buf(), format);
+        store.load().unwrap();
+        Box::new(store)
+    }
+
+    #[test]
+    fn stores_records_in_json_and_yaml_files() {
+        let directory = directory("files");
+        for (file, format) in [("hosts.json", FileFormat::Json), ("hosts.yaml", FileFormat::Yaml)] {
+            let path = directory.join(file);
+            round_trip(&|| file_store(&path, format));
+        }
+        // The files are read back by the store chosen from their extension.
+        let table = ("hosts", "host", "parameters");
+        let json: Box<dyn Store<String>> = open(&directory.join("hosts.json"), table).unwrap();
+        let yaml: Box<dyn Store<String>> = open(&directory.join("hosts.yaml"), table).unwrap();
+        assert_eq!(json.load().unwrap(), records(&[("r1", "d"), ("r3", "e")]));
+        assert_eq!(yaml.load().unwrap(), json.load().unwrap());
+        assert!(fs::read_to_string(directory.join("hosts.yaml")).unwrap().contains("r3: e"));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn stores_records_in_sqlite() {
+        let directory = directory("sqlite");
+        let path = directory.join("hosts.db");
+        round_trip(&|| Box::new(SqliteStore::open(&path, ("hosts", "host", "parameters")).unwrap()));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn keeps_every_record_when_a_write_fails() {
+        let directory = directory("failure");
+        let path = directory.join("hosts.json");
+        let store = file_store(&path, FileFormat::Json);
+        store.put("r1", &"a".to_string()).unwrap();
+
+        // The temporary file cannot be created once the directory is gone.
+        fs::remove_dir_all(&directory).unwrap();
+        let changes = [("r1".to_string(), "b".to_string()), ("r2".to_string(), "c".to_string())];
+        assert!(store.put_all(&changes).is_err());
+        fs::create_dir_all(&directory).unwrap();
+        store.put("r3", &"d".to_string()).unwrap();
+        assert_eq!(file_store(&path, FileFormat::Json).load().unwrap(), records(&[("r1", "a"), ("r3", "d")]));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,325 @@
+use base64::engine::general_purpose::STANDARD_NO_PAD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use ssh2::{HashType, HostKeyType, Session};
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Default location of the known hosts file, overridden by `NETCONF_KNOWN_HOSTS_FILE`.
+const DEFAULT_KNOWN_HOSTS_FILE: &str = "known_hosts.json";
+
+/// How host keys of devices without a trusted fingerprint are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostKeyPolicy {
+    /// Trust on first use: the first key seen for a host is trusted automatically.
+    TrustOnFirstUse,
+    /// Only keys approved through the API are accepted.
+    Strict,
+}
+
+/// A host key identified by its type and SHA256 fingerprint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostKey {
+    /// The key algorithm, e.g. `ssh-ed25519`.
+    pub key_type: String,
+    /// The fingerprint in OpenSSH format, e.g. `SHA256:...`.
+    pub fingerprint: String,
+}
+
+/// Host keys known for a single host.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KnownHost {
+    /// Fingerprints accepted when connecting to the host.
+    pub trusted: Vec<HostKey>,
+    /// The last key offered by the host that is not trusted, waiting for approval.
+    pub pending: Option<HostKey>,
+    /// Whether trusted keys were revoked since the last approval, in which case a new key
+    /// must be approved even with trust-on-first-use.
+    #[serde(default)]
+    pub revoked: bool,
+}
+
+/// Error returned when the host key of a device cannot be accepted.
+#[derive(Debug)]
+pub enum HostKeyError {
+    /// The device did not present a host key.
+    Unavailable { host: String },
+    /// The device presented a key that differs from the trusted ones.
+    Mismatch { host: String, offered: HostKey },
+    /// The device is unknown and the policy is strict, or its keys were revoked.
+    NotApproved { host: String, offered: HostKey },
+    /// The store could not be written.
+    Unsaved { host: String, cause: io::Error },
+}
+
+impl fmt::Display for HostKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HostKeyError::Unavailable { host } => {
+                write!(f, "host {} did not present a host key", host)
+            }
+            HostKeyError::Mismatch { host, offered } => write!(
+                f,
+                "host key mismatch for {}: offered {} {} is not trusted",
+                host, offered.key_type, offered.fingerprint
+            ),
+            HostKeyError::NotApproved { host, offered } => write!(
+                f,
+                "host key {} {} of {} is pending approval",
+                offered.key_type, offered.fingerprint, host
+            ),
+            HostKeyError::Unsaved { host, cause } => {
+                write!(f, "cannot save the host keys of {}: {}", host, cause)
+            }
+        }
+    }
+}
+
+impl Error for HostKeyError {}
+
+/// Store of trusted SSH host keys, persisted as JSON.
+pub struct KnownHosts {
+    /// The policy applied to hosts without a trusted key.
+    policy: HostKeyPolicy,
+    /// The file the store is written to after every change.
+    path: PathBuf,
+    /// Known keys per host name.
+    hosts: Mutex<HashMap<String, KnownHost>>,
+}
+
+impl KnownHosts {
+    /// Loads the store from the `NETCONF_KNOWN_HOSTS_FILE` file, using the policy
+    /// given by `NETCONF_HOST_KEY_POLICY` (`tofu` or `strict`, default `tofu`).
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the store, or an error if the file exists but cannot be read.
+    pub fn from_env() -> io::Result<Self> {
+        let path = PathBuf::from(
+            env::var("NETCONF_KNOWN_HOSTS_FILE").unwrap_or_else(|_| DEFAULT_KNOWN_HOSTS_FILE.to_string()),
+        );
+        let policy = match env::var("NETCONF_HOST_KEY_POLICY").as_deref() {
+            Ok("strict") => HostKeyPolicy::Strict,
+            Ok("tofu") | Err(_) => HostKeyPolicy::TrustOnFirstUse,
+            Ok(other) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unknown NETCONF_HOST_KEY_POLICY {:?}", other),
+                ))
+            }
+        };
+        let hosts = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(KnownHosts { policy, path, hosts: Mutex::new(hosts) })
+    }
+
+    /// Writes the store to its file.
+    ///
+    /// The callers change a copy of the hosts and only keep it once it is written, so a
+    /// change that cannot be saved is not applied either.
+    fn save(&self, hosts: &HashMap<String, KnownHost>) -> io::Result<()> {
+        let content = serde_json::to_string_pretty(hosts)?;
+        fs::write(&self.path, content)
+    }
+
+    /// Checks the host key presented by a device after the SSH handshake.
+    ///
+    /// With trust-on-first-use, the key of a host seen for the first time is trusted, unless
+    /// its keys were revoked. Otherwise an untrusted key is recorded as pending so it can be
+    /// approved.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The hostname or IP address of the device.
+    /// * `sess` - The SSH session, after the handshake.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` which is a `HostKeyError` if the key is not trusted or cannot be saved.
+    pub fn verify(&self, host: &str, sess: &Session) -> Result<(), HostKeyError> {
+        let offered = match (sess.host_key(), sess.host_key_hash(HashType::Sha256)) {
+            (Some((_, key_type)), Some(hash)) => HostKey {
+                key_type: key_type_name(key_type).to_string(),
+                fingerprint: format!("SHA256:{}", STANDARD_NO_PAD.encode(hash)),
+            },
+            _ => return Err(HostKeyError::Unavailable { host: host.to_string() }),
+        };
+        self.check(host, offered)
+    }
+
+    /// Checks a host key against the store, trusting or recording it as `verify` describes.
+    fn check(&self, host: &str, offered: HostKey) -> Result<(), HostKeyError> {
+        let mut hosts = self.hosts.lock().unwrap();
+        if hosts.get(host).is_some_and(|known| known.trusted.contains(&offered)) {
+            return Ok(());
+        }
+
+        let mut updated = hosts.clone();
+        let known = updated.entry(host.to_string()).or_default();
+        let first_use = known.trusted.is_empty();
+        let result = if first_use && !known.revoked && self.policy == HostKeyPolicy::TrustOnFirstUse {
+            known.trusted.push(offered);
+            known.pending = None;
+            Ok(())
+        } else if first_use {
+            known.pending = Some(offered.clone());
+            Err(HostKeyError::NotApproved { host: host.to_string(), offered })
+        } else {
+            known.pending = Some(offered.clone());
+            Err(HostKeyError::Mismatch { host: host.to_string(), offered })
+        };
+        self.save(&updated)
+            .map_err(|cause| HostKeyError::Unsaved { host: host.to_string(), cause })?;
+        *hosts = updated;
+        result
+    }
+
+    /// Returns the keys known for every host.
+    pub fn list(&self) -> HashMap<String, KnownHost> {
+        self.hosts.lock().unwrap().clone()
+    }
+
+    /// Returns the keys known for a host.
+    pub fn get(&self, host: &str) -> Option<KnownHost> {
+        self.hosts.lock().unwrap().get(host).cloned()
+    }
+
+    /// Trusts a fingerprint for a host.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The hostname or IP address of the device.
+    /// * `fingerprint` - The fingerprint to trust. If it matches the pending key, the
+    ///   pending key is moved to the trusted ones.
+    /// * `key_type` - The key type, required when the fingerprint is not the pending one.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the updated entry, or an error of kind `InvalidInput` if the
+    /// key type is unknown, or any other error if the store cannot be written.
+    pub fn approve(&self, host: &str, fingerprint: &str, key_type: Option<&str>) -> io::Result<KnownHost> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let mut updated = hosts.clone();
+        let known = updated.entry(host.to_string()).or_default();
+        let key = match (&known.pending, key_type) {
+            (Some(pending), _) if pending.fingerprint == fingerprint => {
+                known.pending.take().unwrap()
+            }
+            (_, Some(key_type)) => HostKey {
+                key_type: key_type.to_string(),
+                fingerprint: fingerprint.to_string(),
+            },
+            (_, None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} is not the pending fingerprint of {}, key_type is required", fingerprint, host),
+                ))
+            }
+        };
+        if !known.trusted.contains(&key) {
+            known.trusted.push(key);
+        }
+        known.revoked = false;
+        let known = known.clone();
+        self.save(&updated)?;
+        *hosts = updated;
+        Ok(known)
+    }
+
+    /// Stops trusting a fingerprint for a host.
+    ///
+    /// The fingerprint is removed from the trusted keys and from the pending key. Once a
+    /// trusted key is revoked, the next key of the host must be approved.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the updated entry, `None` if the fingerprint is not known,
+    /// or an error if the store cannot be written.
+    pub fn revoke(&self, host: &str, fingerprint: &str) -> io::Result<Option<KnownHost>> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let mut updated = hosts.clone();
+        let Some(known) = updated.get_mut(host) else {
+            return Ok(None);
+        };
+        let before = known.trusted.len();
+        known.trusted.retain(|k| k.fingerprint != fingerprint);
+        let trusted = known.trusted.len() != before;
+        let pending = known.pending.as_ref().is_some_and(|k| k.fingerprint == fingerprint);
+        if pending {
+            known.pending = None;
+        }
+        if !trusted && !pending {
+            return Ok(None);
+        }
+        known.revoked |= trusted;
+        let known = known.clone();
+        self.save(&updated)?;
+        *hosts = updated;
+        Ok(Some(known))
+    }
+}
+
+/// Returns the OpenSSH name of a host key type.
+fn key_type_name(key_type: HostKeyType) -> &'static str {
+    match key_type {
+        HostKeyType::Rsa => "ssh-rsa",
+        HostKeyType::Dss => "ssh-dss",
+        HostKeyType::Ecdsa256 => "ecdsa-sha2-nistp256",
+        HostKeyType::Ecdsa384 => "ecdsa-sha2-nistp384",
+        HostKeyType::Ecdsa521 => "ecdsa-sha2-nistp521",
+        HostKeyType::Ed255219 => "ssh-ed25519",
+        HostKeyType::Unknown => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(name: &str, policy: HostKeyPolicy) -> KnownHosts {
+        let path = env::temp_dir().join(format!("known_hosts_{}_{}.json", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        KnownHosts { policy, path, hosts: Mutex::new(HashMap::new()) }
+    }
+
+    fn key(fingerprint: &str) -> HostKey {
+        HostKey { key_type: "ssh-ed25519".to_string(), fingerprint: fingerprint.to_string() }
+    }
+
+    #[test]
+    fn revoked_hosts_need_approval_with_trust_on_first_use() {
+        let known_hosts = store("revoked", HostKeyPolicy::TrustOnFirstUse);
+        assert!(known_hosts.check("r1", key("SHA256:a")).is_ok());
+        assert!(known_hosts.revoke("r1", "SHA256:a").unwrap().unwrap().revoked);
+
+        let result = known_hosts.check("r1", key("SHA256:b"));
+        assert!(matches!(result, Err(HostKeyError::NotApproved { .. })));
+        assert_eq!(known_hosts.get("r1").unwrap().pending, Some(key("SHA256:b")));
+
+        let approved = known_hosts.approve("r1", "SHA256:b", None).unwrap();
+        assert!(!approved.revoked);
+        assert!(known_hosts.check("r1", key("SHA256:b")).is_ok());
+        let _ = fs::remove_file(&known_hosts.path);
+    }
+
+    #[test]
+    fn changes_that_cannot_be_saved_are_not_applied() {
+        let mut known_hosts = store("unsaved", HostKeyPolicy::TrustOnFirstUse);
+        known_hosts.path = env::temp_dir().join("missing_directory").join("known_hosts.json");
+
+        let result = known_hosts.check("r1", key("SHA256:a"));
+        assert!(matches!(result, Err(HostKeyError::Unsaved { .. })));
+        assert!(known_hosts.get("r1").is_none());
+        assert!(known_hosts.approve("r1", "SHA256:a", Some("ssh-ed25519")).is_err());
+        assert!(known_hosts.get("r1").is_none());
+    }
+}
@@ -1,38 +1,128 @@
+mod ansible;
+mod collector;
+mod credentials;
+mod errors;
+mod host_store;
+mod known_hosts;
+mod metric_config;
 mod netconf;
 mod utils;
 mod routes;
 mod models;
 mod opt_utils;
+mod poller;
+mod session;
+mod tags;
+mod thresholds;
 
 use routes::{
     get_json::get_json,
+    get_data::get_data,
     add_host::add_host,
+    collect::collect_hosts,
     get_hosts::get_hosts,
+    hosts::{create_host, delete_host, get_host, list_hosts, replace_host, update_host},
+    hosts_bulk::{export_hosts, import_hosts},
+    inventory::inventory,
+    out_of_limits::out_of_limits,
+    poll_status::poll_status,
+    probe::probe,
+    profiles::{delete_profile, get_profile, list_profiles, put_profile},
+    rpc::rpc,
+    known_hosts::{approve_host_key, get_known_host, list_known_hosts, revoke_host_key},
 };
-use models::ComponentMetrics;
+use credentials::{plain_text_allowed_from_env, Cipher, CredentialProfiles};
+use host_store::HostStore;
+use known_hosts::KnownHosts;
+use collector::{HostStatus, InventoryItem};
+use errors::ApiError;
+use metric_config::MetricsConfig;
+use models::{ConfiguredMetric, PollMetrics, TrackedGauge};
+use netconf::Timeouts;
 use opt_utils::register_init_metric;
+use poller::Poller;
+use session::SessionPool;
+use thresholds::ChannelStatus;
 
 use actix_web::{web, App, HttpServer};
 use actix_web_prom::PrometheusMetricsBuilder;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 /// State structure for the Actix web application.
 #[derive(Clone, Debug)]
 pub struct AppState {
-    /// Metric data for optical components.
-    optic_data: ComponentMetrics,
+    /// Metrics declared in the metric definition file.
+    metrics: Arc<Vec<ConfiguredMetric>>,
+    /// Timestamps of the last collections of every host.
+    poll_metrics: PollMetrics,
+    /// Outcome of the last collections, per host.
+    poll_status: Arc<Mutex<HashMap<String, HostStatus>>>,
+    /// Transceivers found in the last successful collection, per host.
+    inventory: Arc<Mutex<HashMap<String, Vec<InventoryItem>>>>,
+    /// Status of the channels compared with their thresholds, per host.
+    channel_status: Arc<Mutex<HashMap<String, Vec<ChannelStatus>>>>,
+    /// Gauge exporting the channel status as 0 (ok), 1 (warning) or 2 (alarm).
+    channel_status_metric: TrackedGauge,
+    /// Host tags added as labels to every metric.
+    tag_labels: Arc<Vec<String>>,
+    /// Values of the tag labels of the series of every host, to replace them when the tags change.
+    series_tags: Arc<Mutex<HashMap<String, Vec<String>>>>,
 }
 
-/// Structure to hold host parameters including port, user, and password.
-#[derive(Clone, Debug)]
+/// Private key used for SSH public-key authentication.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivateKey {
+    /// A PEM encoded private key.
+    Inline(String),
+    /// Path to a PEM encoded private key file.
+    File(PathBuf),
+}
+
+/// SSH authentication method configured for a host.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    /// Public-key authentication with an optional passphrase for the key.
+    PrivateKey {
+        key: PrivateKey,
+        passphrase: Option<String>,
+    },
+    /// Authentication with the identities held by the local ssh-agent.
+    Agent,
+    /// Password authentication.
+    Password(String),
+}
+
+/// Structure to hold host parameters including port, user, and authentication methods.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct HostParameters {
     /// The port number for the host.
-    port: isize,
+    port: u16,
     /// The username for the host.
     user: String,
-    /// The password for the host.
-    password: String,
+    /// The authentication methods for the host, tried in order.
+    /// Stored as `{method: value}` maps, so YAML files do not need tags.
+    #[serde(with = "serde_yaml::with::singleton_map_recursive")]
+    auth_methods: Vec<AuthMethod>,
+    /// The timeouts for the host, overriding the global defaults.
+    #[serde(default)]
+    timeouts: Timeouts,
+    /// Seconds between background collections, overriding the global default. `0` disables polling.
+    #[serde(default)]
+    poll_interval: Option<u64>,
+    /// The credential profile replacing the user and authentication methods of the host.
+    #[serde(default)]
+    credential_profile: Option<String>,
+    /// Metadata of the host, such as its site, role or vendor.
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+    /// The groups of the host.
+    #[serde(default)]
+    groups: BTreeSet<String>,
 }
 
 /// Main entry point for the Actix web application.
@@ -45,13 +135,38 @@ pub struct HostParameters {
 /// A `Result` indicating success or failure of the server setup.
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Create a thread-safe dictionary to store host parameters.
-    let host_dictionary: Arc<Mutex<HashMap<String, HostParameters>>> = Arc::new(Mutex::new(HashMap::new()));
+    // Load the master key encrypting the secrets of the stores.
+    let cipher = Cipher::from_env()?;
+    if cipher.is_none() && plain_text_allowed_from_env() {
+        println!("No NETCONF_MASTER_KEY or NETCONF_MASTER_KEY_FILE, device secrets are stored in plain text");
+    } else if cipher.is_none() {
+        println!("No NETCONF_MASTER_KEY or NETCONF_MASTER_KEY_FILE, hosts and profiles with secrets are refused");
+    }
+    // Open the persistent host inventory and load the hosts into a thread-safe dictionary.
+    let host_store: Arc<HostStore> = Arc::from(host_store::hosts_from_env(cipher.clone())?);
+    let host_dictionary: Arc<Mutex<HashMap<String, HostParameters>>> = Arc::new(Mutex::new(host_store.load()?));
+    let host_store = web::Data::from(host_store);
+    // Load the credential profiles shared by the hosts.
+    let profiles = Arc::new(CredentialProfiles::load(host_store::profiles_from_env(cipher)?)?);
+    // Load the store of trusted SSH host keys.
+    let known_hosts = Arc::new(KnownHosts::from_env()?);
     // Create a new Prometheus registry.
     let registry = prometheus::Registry::new();
 
-    // Initialize the application state with metrics.
-    let app_state = register_init_metric(registry.clone());
+    // Initialize the application state with the metrics of the definition file.
+    let metrics_config = web::Data::new(MetricsConfig::from_env()?);
+    let app_state = register_init_metric(registry.clone(), &metrics_config)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+    // Create the pool of persistent NETCONF sessions shared by all workers.
+    let session_pool = web::Data::new(SessionPool::from_env(known_hosts.clone(), profiles.clone(), app_state.clone()));
+    let profiles = web::Data::from(profiles);
+    let known_hosts = web::Data::from(known_hosts);
+
+    // Start polling the registered hosts in the background.
+    actix_web::rt::spawn(
+        Poller::from_env(host_dictionary.clone(), session_pool.clone(), app_state.clone()).run(),
+    );
 
     // Configure Prometheus metrics.
     let prometheus = PrometheusMetricsBuilder::new("api")
@@ -65,10 +180,42 @@ async fn main() -> std::io::Result<()> {
         App::new()
             .app_data(web::Data::new(app_state.clone()))
             .app_data(web::Data::new(host_dictionary.clone()))
+            .app_data(host_store.clone())
+            .app_data(profiles.clone())
+            .app_data(session_pool.clone())
+            .app_data(known_hosts.clone())
+            .app_data(metrics_config.clone())
+            // Report malformed bodies and query strings like the other API errors.
+            .app_data(web::JsonConfig::default().error_handler(|e, _| ApiError::BadRequest(e.to_string()).into()))
+            .app_data(web::QueryConfig::default().error_handler(|e, _| ApiError::BadRequest(e.to_string()).into()))
             .wrap(prometheus.clone())
             .service(get_json)
+            .service(collect_hosts)
             .service(add_host)
             .service(get_hosts)
+            .service(list_hosts)
+            .service(create_host)
+            // Registered before /hosts/{host}, which would match them.
+            .service(import_hosts)
+            .service(export_hosts)
+            .service(get_host)
+            .service(replace_host)
+            .service(update_host)
+            .service(delete_host)
+            .service(rpc)
+            .service(get_data)
+            .service(poll_status)
+            .service(probe)
+            .service(inventory)
+            .service(out_of_limits)
+            .service(list_profiles)
+            .service(get_profile)
+            .service(put_profile)
+            .service(delete_profile)
+            .service(list_known_hosts)
+            .service(get_known_host)
+            .service(approve_host_key)
+            .service(revoke_host_key)
     })
     .bind(("0.0.0.0", 4000))?
     .run()
@@ -0,0 +1,389 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::collector::{as_list, value_to_f64, value_to_i64, value_to_label};
+use crate::tags;
+
+/// Metric definitions used when no definition file is found.
+const DEFAULT_METRICS: &str = include_str!("../metrics.yaml");
+
+/// Default location of the metric definition file, overridden by `NETCONF_METRICS_FILE`.
+const DEFAULT_METRICS_FILE: &str = "metrics.yaml";
+
+/// Labels of the built-in metrics, which tag labels cannot reuse.
+const BUILTIN_LABELS: [&str; 6] = ["host", "rpc", "reason", "component", "channel", "quantity"];
+
+/// Kind of Prometheus metric generated from a definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricKind {
+    /// A gauge set to the value read from the device.
+    #[default]
+    Gauge,
+    /// A counter following the cumulative value read from the device.
+    Counter,
+    /// A gauge set to `1` for every match, carrying the data in its labels.
+    Info,
+}
+
+/// How the value read from the device is converted to a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    /// A decimal number.
+    #[default]
+    Float,
+    /// An integer number.
+    Integer,
+    /// `true` or `false`, exported as `1` or `0`.
+    Boolean,
+    /// A string mapped to a number through the `values` table.
+    Enum,
+}
+
+/// Condition restricting a metric to the entries where a leaf has a given value.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Condition {
+    /// Location of the leaf, using the same syntax as `path`.
+    pub path: String,
+    /// The value the leaf must have.
+    pub equals: String,
+}
+
+/// A metric declared in the definition file.
+///
+/// Locations are JSON paths into the reply converted by `utils::to_json`, such as
+/// `/rpc-reply/data/components/component[]/name`. A segment ending in `[]` is a list:
+/// the metric gets one series per entry. Labels and conditions are resolved in the
+/// context of the same list entries as the value. Their locations may list alternatives
+/// separated by `|`, the first one found is used. A label location ending in `[]` gives
+/// the position of the list entry.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MetricDefinition {
+    /// The metric name.
+    pub name: String,
+    /// The help text of the metric.
+    pub help: String,
+    /// The kind of metric.
+    #[serde(default, rename = "type")]
+    pub kind: MetricKind,
+    /// How the value is converted to a number.
+    #[serde(default)]
+    pub value_type: ValueType,
+    /// Location of the value.
+    pub path: String,
+    /// Label names and the location of their value. The `host` label is always added.
+    /// If a label value is missing, the label is empty.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Labels holding YANG identities, whose module prefix (`oc-opt-types:`) is removed
+    /// since it differs between devices.
+    #[serde(default)]
+    pub strip_prefix: Vec<String>,
+    /// Numbers of the strings of an `enum` value.
+    #[serde(default)]
+    pub values: HashMap<String, f64>,
+    /// Optional condition on the entries exported.
+    pub when: Option<Condition>,
+}
+
+/// The content of the metric definition file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MetricsConfig {
+    /// The metrics to export.
+    pub metrics: Vec<MetricDefinition>,
+    /// Named subsets of the metrics, selected by the `module` parameter of `/probe`.
+    /// Every pattern is a metric name, or a name prefix followed by `*`.
+    #[serde(default)]
+    pub modules: HashMap<String, Vec<String>>,
+    /// Host tags added as labels, after `host`, to every metric of the hosts. Hosts without
+    /// the tag get an empty label.
+    #[serde(default)]
+    pub tag_labels: Vec<String>,
+}
+
+impl MetricsConfig {
+    /// Loads the definitions from the `NETCONF_METRICS_FILE` file.
+    ///
+    /// Without the variable, `metrics.yaml` in the working directory is used if it
+    /// exists, and the built-in definitions otherwise.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the definitions, or an error if the file cannot be read or is invalid.
+    pub fn from_env() -> io::Result<Self> {
+        let content = match env::var("NETCONF_METRICS_FILE") {
+            Ok(path) => fs::read_to_string(path)?,
+            Err(_) if Path::new(DEFAULT_METRICS_FILE).exists() => fs::read_to_string(DEFAULT_METRICS_FILE)?,
+            Err(_) => DEFAULT_METRICS.to_string(),
+        };
+        Self::parse(&content)
+    }
+
+    /// Parses and validates metric definitions written in YAML.
+    pub fn parse(content: &str) -> io::Result<Self> {
+        let config: MetricsConfig = serde_yaml::from_str(content)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        for metric in &config.metrics {
+            metric
+                .validate()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("metric {}: {}", metric.name, e)))?;
+        }
+        for (position, label) in config.tag_labels.iter().enumerate() {
+            if !tags::is_valid_key(label) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("tag label {} is not a valid label name", label),
+                ));
+            }
+            if BUILTIN_LABELS.contains(&label.as_str())
+                || config.tag_labels[..position].contains(label)
+                || config.metrics.iter().any(|m| m.labels.contains_key(label))
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("tag label {} is already a label", label),
+                ));
+            }
+        }
+        for (module, patterns) in &config.modules {
+            if let Some(pattern) = patterns
+                .iter()
+                .find(|p| !config.metrics.iter().any(|m| matches_pattern(&m.name, p)))
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("module {}: {} matches no metric", module, pattern),
+                ));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Returns the definitions of a module.
+    ///
+    /// # Arguments
+    ///
+    /// * `module` - The name of the module, or `None` for every metric.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` containing the definitions of the module, or `None` if the module is unknown.
+    pub fn module(&self, module: Option<&str>) -> Option<MetricsConfig> {
+        let metrics = match module {
+            None => self.metrics.clone(),
+            Some(module) => {
+                let patterns = self.modules.get(module)?;
+                self.metrics
+                    .iter()
+                    .filter(|m| patterns.iter().any(|p| matches_pattern(&m.name, p)))
+                    .cloned()
+                    .collect()
+            }
+        };
+        Some(MetricsConfig { metrics, modules: HashMap::new(), tag_labels: self.tag_labels.clone() })
+    }
+}
+
+/// Returns whether a metric name matches a module pattern.
+fn matches_pattern(name: &str, pattern: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == pattern,
+    }
+}
+
+/// A segment of a location.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Segment<'a> {
+    /// The key of the JSON object.
+    name: &'a str,
+    /// Whether the key holds a list whose entries are iterated.
+    list: bool,
+}
+
+/// Splits a location into segments.
+fn segments(path: &str) -> Vec<Segment<'_>> {
+    path.split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| match s.strip_suffix("[]") {
+            Some(name) => Segment { name, list: true },
+            None => Segment { name: s, list: false },
+        })
+        .collect()
+}
+
+/// A list entry reached while walking the value location.
+struct Binding<'v> {
+    /// Number of segments of the location up to and including the list.
+    depth: usize,
+    /// The list entry.
+    value: &'v Value,
+    /// The position of the entry in the list.
+    position: usize,
+}
+
+impl MetricDefinition {
+    /// Checks that labels and conditions can be resolved in the context of the value.
+    fn validate(&self) -> Result<(), String> {
+        if self.kind == MetricKind::Counter && self.value_type == ValueType::Enum {
+            return Err("counters cannot have an enum value".to_string());
+        }
+        if self.value_type == ValueType::Enum && self.values.is_empty() {
+            return Err("enum values require a values table".to_string());
+        }
+        if self.labels.contains_key("host") {
+            return Err("the host label is reserved".to_string());
+        }
+        if let Some(label) = self.strip_prefix.iter().find(|l| !self.labels.contains_key(*l)) {
+            return Err(format!("strip_prefix names the unknown label {}", label));
+        }
+        let path = segments(&self.path);
+        let conditions = self.when.iter().map(|c| ("when", c.path.as_str()));
+        let locations = self
+            .labels
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .chain(conditions)
+            .flat_map(|(name, location)| location.split('|').map(move |l| (name, l.trim())));
+        for (name, location) in locations {
+            let location = segments(location);
+            let lists = location.iter().rposition(|s| s.list).map_or(0, |i| i + 1);
+            if path.len() < lists || location[..lists] != path[..lists] {
+                return Err(format!("{} does not share the lists of the value path", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the label names of the metric, starting with `host`.
+    pub fn label_names(&self) -> Vec<&str> {
+        let mut names = vec!["host"];
+        names.extend(self.labels.keys().map(|k| k.as_str()));
+        names
+    }
+
+    /// Extracts the series of the metric from a reply converted to JSON.
+    ///
+    /// # Arguments
+    ///
+    /// * `reply` - The reply converted by `utils::to_json`.
+    ///
+    /// # Returns
+    ///
+    /// The label values (without `host`) and the value of every series found.
+    /// Entries whose value is missing or cannot be converted are skipped.
+    pub fn samples(&self, reply: &Value) -> Vec<(Vec<String>, f64)> {
+        let path = segments(&self.path);
+        let mut samples = vec![];
+        let mut bindings = vec![];
+        walk(reply, &path, 0, &mut bindings, &mut |value, bindings| {
+            if let Some(condition) = &self.when {
+                if resolve_first(reply, &condition.path, bindings).as_deref() != Some(condition.equals.as_str()) {
+                    return;
+                }
+            }
+            if let Some(number) = self.convert(value) {
+                let labels = self
+                    .labels
+                    .iter()
+                    .map(|(name, location)| {
+                        let label = resolve_first(reply, location, bindings).unwrap_or_default();
+                        match label.split_once(':') {
+                            Some((_, identity)) if self.strip_prefix.contains(name) => identity.to_string(),
+                            _ => label,
+                        }
+                    })
+                    .collect();
+                samples.push((labels, number));
+            }
+        });
+        samples
+    }
+
+    /// Converts a value read from the device according to the metric type.
+    fn convert(&self, value: &Value) -> Option<f64> {
+        if self.kind == MetricKind::Info {
+            return Some(1.0);
+        }
+        match self.value_type {
+            ValueType::Float => value_to_f64(value),
+            ValueType::Integer => value_to_i64(value).map(|v| v as f64),
+            ValueType::Boolean => match value.as_str() {
+                Some("true") => Some(1.0),
+                Some("false") => Some(0.0),
+                _ => None,
+            },
+            ValueType::Enum => value.as_str().and_then(|v| self.values.get(v).copied()),
+        }
+    }
+}
+
+/// Follows a location, calling `found` for every value reached.
+fn walk<'v>(
+    value: &'v Value,
+    path: &[Segment],
+    depth: usize,
+    bindings: &mut Vec<Binding<'v>>,
+    found: &mut dyn FnMut(&'v Value, &[Binding<'v>]),
+) {
+    let segment = match path.get(depth) {
+        Some(segment) => segment,
+        None => return found(value, bindings),
+    };
+    let child = value.get(segment.name);
+    if !segment.list {
+        if let Some(child) = child {
+            walk(child, path, depth + 1, bindings, found);
+        }
+        return;
+    }
+    for (position, entry) in as_list(child).into_iter().enumerate() {
+        bindings.push(Binding { depth: depth + 1, value: entry, position });
+        walk(entry, path, depth + 1, bindings, found);
+        bindings.pop();
+    }
+}
+
+/// Returns the list entry of the innermost list of a label or condition location.
+///
+/// Validation guarantees that the lists of the location are lists of the value path.
+fn innermost<'b, 'v>(location: &[Segment], bindings: &'b [Binding<'v>]) -> Option<&'b Binding<'v>> {
+    let depth = location.iter().rposition(|s| s.list)? + 1;
+    bindings.iter().find(|b| b.depth == depth)
+}
+
+/// Resolves a label or condition location from the list entry it shares with the value.
+fn resolve<'v>(reply: &'v Value, location: &[Segment], bindings: &[Binding<'v>]) -> Option<&'v Value> {
+    let (start, value) = innermost(location, bindings).map_or((0, reply), |b| (b.depth, b.value));
+    location[start..]
+        .iter()
+        .try_fold(value, |value, segment| value.get(segment.name))
+}
+
+/// Returns the value of the first alternative of a location separated by `|` that is
+/// found, like label locations, with every alternative relative to `value`.
+pub fn resolve_alternatives(value: &Value, location: &str) -> Option<String> {
+    location
+        .split('|')
+        .find_map(|alternative| resolve(value, &segments(alternative.trim()), &[]).map(value_to_label))
+}
+
+/// Returns the value of the first alternative of a label or condition location that is found.
+///
+/// An alternative ending in a list gives the position of the list entry.
+fn resolve_first(reply: &Value, location: &str, bindings: &[Binding]) -> Option<String> {
+    location.split('|').find_map(|alternative| {
+        let alternative = segments(alternative.trim());
+        if alternative.last().is_some_and(|s| s.list) {
+            innermost(&alternative, bindings).map(|b| b.position.to_string())
+        } else {
+            resolve(reply, &alternative, bindings).map(value_to_label)
+        }
+    })
+}
@@ -1,39 +1,201 @@
-use prometheus::GaugeVec;
+use prometheus::core::{Collector, MetricVecBuilder};
+use prometheus::{CounterVec, GaugeVec};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-/// Represents a set of advanced metrics for a particular measurement type.
+use crate::metric_config::MetricDefinition;
+
+/// Prometheus collector generated from a metric definition.
 #[derive(Debug, Clone)]
-pub struct AdvanceMetric {
-    /// The average value metric.
-    pub avg: GaugeVec,
-    /// The instant value metric.
-    pub instant: GaugeVec,
-    /// The interval value metric.
-    pub interval: GaugeVec,
-    /// The maximum value metric.
-    pub max: GaugeVec,
-    /// The maximum time value metric.
-    pub max_time: GaugeVec,
-    /// The minimum value metric.
-    pub min: GaugeVec,
-    /// The minimum time value metric.
-    pub min_time: GaugeVec,
-}
-
-/// Represents metrics for a specific channel, including input power, laser bias current, and output power.
+pub enum MetricVec {
+    /// A gauge, also used for info metrics.
+    Gauge(GaugeVec),
+    /// A counter.
+    Counter(CounterVec),
+}
+
+impl MetricVec {
+    /// Removes a series, ignoring series that do not exist.
+    fn remove(&self, labels: &[&str]) {
+        let _ = match self {
+            MetricVec::Gauge(gauge) => gauge.remove_label_values(labels),
+            MetricVec::Counter(counter) => counter.remove_label_values(labels),
+        };
+    }
+}
+
+/// Last update of every series of a metric, used to remove the stale ones.
+///
+/// The first label of every series is the host.
+#[derive(Debug, Clone, Default)]
+pub struct SeriesTimes(Arc<Mutex<HashMap<Vec<String>, Instant>>>);
+
+impl SeriesTimes {
+    /// Records that a series was just updated.
+    fn touch(&self, labels: &[&str]) {
+        let labels = labels.iter().map(|l| l.to_string()).collect();
+        self.0.lock().unwrap().insert(labels, Instant::now());
+    }
+
+    /// Forgets the series last updated before the cutoff of their host.
+    ///
+    /// # Arguments
+    ///
+    /// * `cutoff` - Returns the time before which the series of a host are forgotten, or
+    ///   `None` to keep them.
+    ///
+    /// # Returns
+    ///
+    /// The labels of the forgotten series.
+    fn expire(&self, cutoff: &dyn Fn(&str) -> Option<Instant>) -> Vec<Vec<String>> {
+        let mut expired = vec![];
+        self.0.lock().unwrap().retain(|labels, updated| {
+            let stale = cutoff(&labels[0]).is_some_and(|before| *updated < before);
+            if stale {
+                expired.push(labels.clone());
+            }
+            !stale
+        });
+        expired
+    }
+}
+
+/// Represents a metric declared in the metric definition file.
 #[derive(Debug, Clone)]
-pub struct ChannelMetrics {
-    /// Metrics related to input power.
-    pub input_power: AdvanceMetric,
-    /// Metrics related to laser bias current.
-    pub laser_bias_current: AdvanceMetric,
-    /// Metrics related to output power.
-    pub output_power: AdvanceMetric,
+pub struct ConfiguredMetric {
+    /// The definition of the metric.
+    pub definition: MetricDefinition,
+    /// The registered Prometheus collector.
+    pub metric: MetricVec,
+    /// The last update of every series.
+    pub times: SeriesTimes,
+}
+
+impl ConfiguredMetric {
+    /// Sets a series to a value read from the device.
+    ///
+    /// Counters follow the cumulative value of the device and are reset when it decreases.
+    pub fn set(&self, labels: &[&str], value: f64) {
+        self.times.touch(labels);
+        match &self.metric {
+            MetricVec::Gauge(gauge) => gauge.with_label_values(labels).set(value),
+            MetricVec::Counter(counter) => {
+                let counter = counter.with_label_values(labels);
+                let current = counter.get();
+                if value < current {
+                    counter.reset();
+                    counter.inc_by(value.max(0.0));
+                } else {
+                    counter.inc_by(value - current);
+                }
+            }
+        }
+    }
+
+    /// Removes the series last updated before the cutoff of their host.
+    ///
+    /// # Arguments
+    ///
+    /// * `cutoff` - Returns the time before which the series of a host are removed, or
+    ///   `None` to keep them.
+    pub fn expire(&self, cutoff: &dyn Fn(&str) -> Option<Instant>) {
+        for labels in self.times.expire(cutoff) {
+            let labels: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+            self.metric.remove(&labels);
+        }
+    }
 }
 
-/// Represents the component metrics, which include channel metrics.
+/// A gauge whose series are removed when they are no longer updated.
 #[derive(Debug, Clone)]
-pub struct ComponentMetrics {
-    /// Channel-specific metrics.
-    pub channel: ChannelMetrics,
+pub struct TrackedGauge {
+    /// The registered gauge.
+    pub gauge: GaugeVec,
+    /// The last update of every series.
+    pub times: SeriesTimes,
 }
 
+impl TrackedGauge {
+    /// Sets a series, the first label being the host.
+    pub fn set(&self, labels: &[&str], value: f64) {
+        self.times.touch(labels);
+        self.gauge.with_label_values(labels).set(value);
+    }
+
+    /// Removes the series last updated before the cutoff of their host, like `ConfiguredMetric::expire`.
+    pub fn expire(&self, cutoff: &dyn Fn(&str) -> Option<Instant>) {
+        for labels in self.times.expire(cutoff) {
+            let labels: Vec<&str> = labels.iter().map(|l| l.as_str()).collect();
+            let _ = self.gauge.remove_label_values(&labels);
+        }
+    }
+}
+
+/// Represents the health of the collections of every host.
+#[derive(Debug, Clone)]
+pub struct PollMetrics {
+    /// `1` if the last collection succeeded, `0` otherwise.
+    pub up: GaugeVec,
+    /// Duration of the last RPC, per host and RPC.
+    pub scrape_duration: GaugeVec,
+    /// Number of failed collections, per host and error kind.
+    pub scrape_errors: CounterVec,
+    /// Size of the last reply.
+    pub reply_bytes: GaugeVec,
+    /// Unix timestamp of the last successful collection.
+    pub last_success: GaugeVec,
+    /// Unix timestamp of the last failed collection.
+    pub last_error: GaugeVec,
+}
+
+impl PollMetrics {
+    /// Removes every series of a host.
+    pub fn forget(&self, host: &str) {
+        remove_host(&self.up, host);
+        remove_host(&self.scrape_duration, host);
+        remove_host(&self.scrape_errors, host);
+        remove_host(&self.reply_bytes, host);
+        remove_host(&self.last_success, host);
+        remove_host(&self.last_error, host);
+    }
+}
+
+/// Removes the series of a metric whose `host` label is the given host, whatever its other labels.
+fn remove_host<T: MetricVecBuilder>(metric: &prometheus::core::MetricVec<T>, host: &str) {
+    for family in metric.collect() {
+        for series in family.get_metric() {
+            let labels: HashMap<&str, &str> = series
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name(), label.get_value()))
+                .collect();
+            if labels.get("host") == Some(&host) {
+                let _ = metric.remove(&labels);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn expires_series_against_the_cutoff_of_their_host() {
+        let times = SeriesTimes::default();
+        times.touch(&["r1", "a"]);
+        times.touch(&["r2", "a"]);
+        times.touch(&["r3", "a"]);
+        let later = Instant::now() + Duration::from_secs(1);
+
+        let expired = times.expire(&|host| match host {
+            "r1" => Some(later),
+            "r2" => Some(later - Duration::from_secs(60)),
+            _ => None,
+        });
+        assert_eq!(expired, vec![vec!["r1".to_string(), "a".to_string()]]);
+        assert_eq!(times.0.lock().unwrap().len(), 2);
+    }
+}
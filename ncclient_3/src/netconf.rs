@@ -1,83 +1,1017 @@
-use ssh2::{Channel, Session};
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use ssh2::{Channel, ErrorCode, Session};
+use std::collections::{BTreeMap, HashSet};
+use std::env;
 use std::error::Error;
-use std::io::prelude::*;
-use std::net::TcpStream;
+use std::fmt;
+use std::io::{self, prelude::*};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use crate::known_hosts::KnownHosts;
+use crate::{AuthMethod, HostParameters, PrivateKey};
+
+/// Capability URN of the base:1.0 protocol (end-of-message framing).
+const BASE_1_0: &str = "urn:ietf:params:netconf:base:1.0";
+
+/// Capability URN of the base:1.1 protocol (chunked framing).
+const BASE_1_1: &str = "urn:ietf:params:netconf:base:1.1";
+
+/// Capability URN of the XPath filters (RFC 6241 section 8.9).
+pub const XPATH: &str = "urn:ietf:params:netconf:capability:xpath:1.0";
+
+/// Capability URN of the with-defaults parameter (RFC 6243).
+pub const WITH_DEFAULTS: &str = "urn:ietf:params:netconf:capability:with-defaults:1.0";
 
 const HELLO: &str = "<hello xmlns=\"urn:ietf:params:xml:ns:netconf:base:1.0\">
   <capabilities>
+    <capability>urn:ietf:params:netconf:base:1.0</capability>
     <capability>urn:ietf:params:netconf:base:1.1</capability>
   </capabilities>
-</hello>
-]]>]]>";
+</hello>";
+
+/// libssh2 error code for invalid arguments, used when the agent holds no identity.
+const LIBSSH2_ERROR_INVAL: i32 = -34;
+
+/// libssh2 error code returned when a blocking call exceeds the session timeout.
+const LIBSSH2_ERROR_TIMEOUT: i32 = -9;
+
+/// Default seconds allowed to establish the TCP connection.
+const DEFAULT_CONNECT_TIMEOUT: u64 = 10;
+
+/// Default seconds allowed for the SSH handshake, authentication and hello exchange.
+const DEFAULT_HANDSHAKE_TIMEOUT: u64 = 30;
+
+/// Default seconds allowed to receive the complete reply of an RPC.
+const DEFAULT_RPC_TIMEOUT: u64 = 120;
+
+/// Seconds between keepalives used to check that an idle session is still alive.
+const KEEPALIVE_INTERVAL: u32 = 30;
+
+/// Size of the blocks requested from the SSH channel on every read.
+const READ_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Delimiter that terminates a message when using base:1.0 end-of-message framing.
+const END_OF_MESSAGE: &[u8] = b"]]>]]>";
+
+/// Largest chunk size allowed by RFC 6242 section 4.2.
+const MAX_CHUNK_SIZE: usize = 4_294_967_295;
+
+/// Timeouts of a NETCONF session, in seconds.
+///
+/// Unset values fall back to the global defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Timeouts {
+    /// Timeout to establish the TCP connection.
+    pub connect: Option<u64>,
+    /// Timeout for the SSH handshake, authentication and hello exchange.
+    pub handshake: Option<u64>,
+    /// Timeout to receive the complete reply of an RPC.
+    pub rpc: Option<u64>,
+}
+
+impl Timeouts {
+    /// Reads the global defaults from the `NETCONF_CONNECT_TIMEOUT`,
+    /// `NETCONF_HANDSHAKE_TIMEOUT` and `NETCONF_RPC_TIMEOUT` environment variables.
+    pub fn from_env() -> Self {
+        let seconds = |name: &str, default: u64| {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(default)
+        };
+        Timeouts {
+            connect: Some(seconds("NETCONF_CONNECT_TIMEOUT", DEFAULT_CONNECT_TIMEOUT)),
+            handshake: Some(seconds("NETCONF_HANDSHAKE_TIMEOUT", DEFAULT_HANDSHAKE_TIMEOUT)),
+            rpc: Some(seconds("NETCONF_RPC_TIMEOUT", DEFAULT_RPC_TIMEOUT)),
+        }
+    }
+
+    /// Returns these timeouts with unset values taken from `defaults`.
+    pub fn or(self, defaults: &Timeouts) -> Timeouts {
+        Timeouts {
+            connect: self.connect.or(defaults.connect),
+            handshake: self.handshake.or(defaults.handshake),
+            rpc: self.rpc.or(defaults.rpc),
+        }
+    }
+
+    fn connect(&self) -> Duration {
+        Duration::from_secs(self.connect.unwrap_or(DEFAULT_CONNECT_TIMEOUT).max(1))
+    }
+
+    fn handshake(&self) -> Duration {
+        Duration::from_secs(self.handshake.unwrap_or(DEFAULT_HANDSHAKE_TIMEOUT).max(1))
+    }
+
+    fn rpc(&self) -> Duration {
+        Duration::from_secs(self.rpc.unwrap_or(DEFAULT_RPC_TIMEOUT).max(1))
+    }
+}
+
+/// Error returned when a stage of a NETCONF session exceeds its timeout.
+#[derive(Debug)]
+pub struct TimeoutError {
+    /// The stage that timed out: `connect`, `handshake` or `rpc`.
+    pub stage: &'static str,
+    /// The timeout that was exceeded.
+    pub timeout: Duration,
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NETCONF {} timed out after {}s", self.stage, self.timeout.as_secs())
+    }
+}
 
-/// Reads data from the given SSH channel until a specific end sequence is encountered.
+impl Error for TimeoutError {}
+
+/// Replaces an error by a `TimeoutError` if it was caused by a timeout.
 ///
 /// # Arguments
 ///
-/// * `channel` - A mutable reference to the SSH channel from which to read data.
+/// * `error` - The error returned by a stage of the session.
+/// * `stage` - The name of the stage.
+/// * `timeout` - The timeout of the stage.
+fn into_timeout(error: Box<dyn Error>, stage: &'static str, timeout: Duration) -> Box<dyn Error> {
+    let timed_out = if let Some(e) = error.downcast_ref::<ssh2::Error>() {
+        e.code() == ErrorCode::Session(LIBSSH2_ERROR_TIMEOUT)
+    } else if let Some(e) = error.downcast_ref::<io::Error>() {
+        matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)
+    } else {
+        false
+    };
+    if timed_out {
+        Box::new(TimeoutError { stage, timeout })
+    } else {
+        error
+    }
+}
+
+/// Converts a timeout into the milliseconds expected by `Session::set_timeout`.
+fn as_millis(timeout: Duration) -> u32 {
+    timeout.as_millis().min(u32::MAX as u128) as u32
+}
+
+/// Opens a TCP connection to a host, trying every resolved address.
+fn connect_tcp(host: &str, port: u16, timeout: Duration) -> Result<TcpStream, Box<dyn Error>> {
+    let mut last_error = io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} did not resolve to any address", host),
+    );
+    for address in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&address, timeout) {
+            Ok(tcp) => return Ok(tcp),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error.into())
+}
+
+/// Builds an `io::Error` describing a violation of the NETCONF framing rules.
+fn framing_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("NETCONF framing error: {}", message))
+}
+
+/// Message framing mechanism negotiated during the hello exchange.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Framing {
+    /// base:1.0 framing, messages end with `]]>]]>`.
+    EndOfMessage,
+    /// base:1.1 chunked framing.
+    Chunked,
+}
+
+/// Capabilities and session identifier announced in the server `<hello>`.
+#[derive(Debug, Clone)]
+pub struct ServerHello {
+    /// The session-id assigned by the server.
+    pub session_id: Option<u32>,
+    /// The capability URIs advertised by the server.
+    pub capabilities: HashSet<String>,
+}
+
+impl ServerHello {
+    /// Parses the `<hello>` message sent by the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `xml` - The hello message without its framing delimiter.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the parsed hello, or an `InvalidData` error if the
+    /// message is malformed, does not advertise any base capability or lacks a session-id.
+    pub fn parse(xml: &str) -> io::Result<Self> {
+        let mut reader = Reader::from_str(xml);
+        reader.config_mut().trim_text(true);
+
+        let mut capabilities = HashSet::new();
+        let mut session_id = None;
+        // Local name of the element whose text is being read.
+        let mut current: Option<Vec<u8>> = None;
+        loop {
+            match reader.read_event() {
+                Ok(Event::Start(ref e)) => current = Some(e.local_name().as_ref().to_vec()),
+                Ok(Event::End(_)) => current = None,
+                Ok(Event::Text(ref e)) => {
+                    let text = e.unescape().map_err(hello_error)?;
+                    match current.as_deref() {
+                        Some(b"capability") => {
+                            capabilities.insert(text.trim().to_string());
+                        }
+                        Some(b"session-id") => {
+                            session_id = Some(text.trim().parse::<u32>().map_err(hello_error)?);
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(Event::Eof) => break,
+                Err(e) => return Err(hello_error(e)),
+                _ => {}
+            }
+        }
+
+        let hello = ServerHello { session_id, capabilities };
+        if !hello.supports(BASE_1_0) && !hello.supports(BASE_1_1) {
+            return Err(hello_error("no base capability advertised"));
+        }
+        // RFC 6241 section 8.1: a server hello must include a session-id.
+        if hello.session_id.is_none() {
+            return Err(hello_error("no session-id assigned"));
+        }
+        Ok(hello)
+    }
+
+    /// Checks whether the server advertised a capability, ignoring any URI parameters.
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities
+            .iter()
+            .any(|c| c.split('?').next() == Some(capability))
+    }
+
+    /// Returns a URI parameter of an advertised capability, e.g. the `basic-mode` of
+    /// `urn:ietf:params:netconf:capability:with-defaults:1.0?basic-mode=explicit`.
+    pub fn parameter(&self, capability: &str, name: &str) -> Option<&str> {
+        self.capabilities.iter().find_map(|c| {
+            let (uri, query) = c.split_once('?')?;
+            if uri != capability {
+                return None;
+            }
+            query
+                .split(['&', ';'])
+                .find_map(|pair| pair.split_once('=').filter(|(key, _)| *key == name))
+                .map(|(_, value)| value)
+        })
+    }
+
+    /// Returns the framing to use once the hello exchange is complete.
+    ///
+    /// Chunked framing is only used when both peers advertise base:1.1.
+    pub fn framing(&self) -> Framing {
+        if self.supports(BASE_1_1) {
+            Framing::Chunked
+        } else {
+            Framing::EndOfMessage
+        }
+    }
+}
+
+/// Error returned when a request needs a capability the server did not advertise.
+#[derive(Debug)]
+pub struct UnsupportedCapability {
+    /// The description of the missing capability.
+    pub message: String,
+}
+
+impl fmt::Display for UnsupportedCapability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for UnsupportedCapability {}
+
+/// Builds an `io::Error` describing an invalid server hello.
+fn hello_error<E: std::fmt::Display>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid NETCONF hello: {}", error))
+}
+
+/// Buffered NETCONF framing layer (RFC 6242) on top of a byte stream.
+///
+/// Data is read from the underlying stream in large blocks and kept in an
+/// internal buffer, so bytes belonging to the next message are never lost.
+/// The framer starts with end-of-message framing, as required for the hello exchange.
+pub struct Framer<S> {
+    /// The underlying stream, usually an SSH channel.
+    stream: S,
+    /// The framing used by `read_message` and `write_message`.
+    framing: Framing,
+    /// Instant after which reads fail with a `TimedOut` error.
+    deadline: Option<Instant>,
+    /// Bytes read from the stream but not consumed yet.
+    buffer: Vec<u8>,
+    /// Offset of the first unconsumed byte in `buffer`.
+    position: usize,
+}
+
+impl<S: Read + Write> Framer<S> {
+    /// Creates a new framer wrapping the given stream.
+    pub fn new(stream: S) -> Self {
+        Framer {
+            stream,
+            framing: Framing::EndOfMessage,
+            deadline: None,
+            buffer: Vec::with_capacity(READ_BLOCK_SIZE),
+            position: 0,
+        }
+    }
+
+    /// Switches the framing used for the following messages.
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing;
+    }
+
+    /// Sets the instant after which reads fail with a `TimedOut` error.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Reads the next message using the current framing.
+    pub fn read_message(&mut self) -> io::Result<String> {
+        match self.framing {
+            Framing::EndOfMessage => self.read_eom(),
+            Framing::Chunked => self.read_chunked(),
+        }
+    }
+
+    /// Writes a message using the current framing.
+    pub fn write_message(&mut self, message: &str) -> io::Result<()> {
+        match self.framing {
+            Framing::EndOfMessage => self.write_eom(message),
+            Framing::Chunked => self.write_chunked(message),
+        }
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Returns the underlying stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Drops consumed bytes and appends the next block read from the stream.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` which is an `UnexpectedEof` error if the stream is closed, or a
+    /// `TimedOut` error if the deadline has passed.
+    fn fill(&mut self) -> io::Result<()> {
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "NETCONF message not received in time"));
+        }
+        self.buffer.drain(..self.position);
+        self.position = 0;
+
+        let length = self.buffer.len();
+        self.buffer.resize(length + READ_BLOCK_SIZE, 0);
+        let bytes_read = loop {
+            match self.stream.read(&mut self.buffer[length..]) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        };
+        let bytes_read = bytes_read.inspect_err(|_| self.buffer.truncate(length))?;
+        self.buffer.truncate(length + bytes_read);
+
+        if bytes_read == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "SSH channel closed in the middle of a NETCONF message",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Consumes and returns the next byte of the stream.
+    fn read_byte(&mut self) -> io::Result<u8> {
+        if self.position == self.buffer.len() {
+            self.fill()?;
+        }
+        let byte = self.buffer[self.position];
+        self.position += 1;
+        Ok(byte)
+    }
+
+    /// Consumes the next byte and checks that it is the expected one.
+    fn expect_byte(&mut self, expected: u8) -> io::Result<()> {
+        if self.read_byte()? != expected {
+            return Err(framing_error(&format!("expected {:?}", expected as char)));
+        }
+        Ok(())
+    }
+
+    /// Consumes exactly `length` bytes and appends them to `message`.
+    fn read_exact_into(&mut self, message: &mut Vec<u8>, mut length: usize) -> io::Result<()> {
+        while length > 0 {
+            if self.position == self.buffer.len() {
+                self.fill()?;
+            }
+            let available = (self.buffer.len() - self.position).min(length);
+            message.extend_from_slice(&self.buffer[self.position..self.position + available]);
+            self.position += available;
+            length -= available;
+        }
+        Ok(())
+    }
+
+    /// Reads a message delimited by the base:1.0 `]]>]]>` end-of-message marker.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the message without the delimiter.
+    pub fn read_eom(&mut self) -> io::Result<String> {
+        // Number of bytes after `position` already known not to start the delimiter.
+        let mut scanned = 0;
+        loop {
+            let pending = &self.buffer[self.position..];
+            if let Some(index) = pending[scanned..]
+                .windows(END_OF_MESSAGE.len())
+                .position(|window| window == END_OF_MESSAGE)
+            {
+                let end = self.position + scanned + index;
+                let message = self.buffer[self.position..end].to_vec();
+                self.position = end + END_OF_MESSAGE.len();
+                return into_string(message);
+            }
+            scanned = pending.len().saturating_sub(END_OF_MESSAGE.len() - 1);
+            self.fill()?;
+        }
+    }
+
+    /// Reads a message encoded with base:1.1 chunked framing.
+    ///
+    /// Every `\n#<chunk-size>\n` header is followed by exactly `chunk-size`
+    /// bytes of data, and the message ends with the `\n##\n` end-of-chunks marker.
+    ///
+    /// # Returns
+    ///
+    /// An `io::Result` containing the joined chunks.
+    pub fn read_chunked(&mut self) -> io::Result<String> {
+        let mut message = Vec::new();
+        loop {
+            self.expect_byte(b'\n')?;
+            self.expect_byte(b'#')?;
+
+            let first = self.read_byte()?;
+            if first == b'#' {
+                self.expect_byte(b'\n')?;
+                if message.is_empty() {
+                    return Err(framing_error("end-of-chunks marker without any chunk"));
+                }
+                return into_string(message);
+            }
+            if !(b'1'..=b'9').contains(&first) {
+                return Err(framing_error("invalid chunk size"));
+            }
+
+            let mut size = (first - b'0') as usize;
+            loop {
+                let byte = self.read_byte()?;
+                if byte == b'\n' {
+                    break;
+                }
+                if !byte.is_ascii_digit() {
+                    return Err(framing_error("invalid chunk size"));
+                }
+                size = size * 10 + (byte - b'0') as usize;
+                if size > MAX_CHUNK_SIZE {
+                    return Err(framing_error("chunk size exceeds the RFC 6242 limit"));
+                }
+            }
+            self.read_exact_into(&mut message, size)?;
+        }
+    }
+
+    /// Writes a message followed by the base:1.0 `]]>]]>` end-of-message marker.
+    ///
+    /// A message holding the marker is refused, as the device would read its end as
+    /// another message.
+    pub fn write_eom(&mut self, message: &str) -> io::Result<()> {
+        if holds_end_of_message(message) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the message holds the ]]>]]> end-of-message marker",
+            ));
+        }
+        self.stream.write_all(message.as_bytes())?;
+        self.stream.write_all(END_OF_MESSAGE)?;
+        self.stream.flush()
+    }
+
+    /// Writes a message as a single base:1.1 chunk followed by the end-of-chunks marker.
+    pub fn write_chunked(&mut self, message: &str) -> io::Result<()> {
+        self.stream.write_all(format!("\n#{}\n", message.len()).as_bytes())?;
+        self.stream.write_all(message.as_bytes())?;
+        self.stream.write_all(b"\n##\n")?;
+        self.stream.flush()
+    }
+}
+
+/// Returns whether a message holds the base:1.0 `]]>]]>` end-of-message marker.
+pub fn holds_end_of_message(message: &str) -> bool {
+    message.as_bytes().windows(END_OF_MESSAGE.len()).any(|window| window == END_OF_MESSAGE)
+}
+
+/// Converts the bytes of a complete message into a `String`.
+fn into_string(message: Vec<u8>) -> io::Result<String> {
+    String::from_utf8(message).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// A single `<rpc-error>` element of an `<rpc-reply>` (RFC 6241 section 4.3).
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RpcError {
+    /// The conceptual layer of the error: `transport`, `rpc`, `protocol` or `application`.
+    pub error_type: Option<String>,
+    /// The error condition, e.g. `access-denied` or `invalid-value`.
+    pub error_tag: Option<String>,
+    /// The severity: `error` or `warning`.
+    pub error_severity: Option<String>,
+    /// The data-model or implementation specific error condition.
+    pub error_app_tag: Option<String>,
+    /// The path of the element associated with the error.
+    pub error_path: Option<String>,
+    /// The human readable description of the error.
+    pub error_message: Option<String>,
+    /// The protocol or data-model specific content of `<error-info>`, by element name.
+    pub error_info: BTreeMap<String, String>,
+}
+
+impl RpcError {
+    /// Checks whether the error makes the RPC fail; warnings do not.
+    pub fn is_error(&self) -> bool {
+        self.error_severity.as_deref() != Some("warning")
+    }
+}
+
+/// Error returned when a device answers an RPC with `<rpc-error>` elements of severity `error`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RpcErrors {
+    /// The errors reported in the reply, including warnings.
+    pub errors: Vec<RpcError>,
+}
+
+impl fmt::Display for RpcErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let descriptions: Vec<String> = self
+            .errors
+            .iter()
+            .filter(|e| e.is_error())
+            .map(|e| {
+                format!(
+                    "{}: {}",
+                    e.error_tag.as_deref().unwrap_or("unknown"),
+                    e.error_message.as_deref().unwrap_or("no message")
+                )
+            })
+            .collect();
+        write!(f, "device returned rpc-error ({})", descriptions.join("; "))
+    }
+}
+
+impl Error for RpcErrors {}
+
+/// Parses the `<rpc-error>` elements of an `<rpc-reply>`.
+///
+/// # Arguments
+///
+/// * `reply` - The `<rpc-reply>` message.
 ///
 /// # Returns
 ///
-/// A `Result` containing the read data as a `String` or an `Error` if reading fails.
-fn read(channel: &mut Channel) -> Result<String, Box<dyn Error>> {
-    let mut result = String::new();
+/// An `io::Result` containing the errors found, or an `InvalidData` error if the reply is malformed.
+pub fn parse_rpc_errors(reply: &str) -> io::Result<Vec<RpcError>> {
+    let mut reader = Reader::from_str(reply);
+    reader.config_mut().trim_text(true);
+
+    let invalid = |e: quick_xml::Error| io::Error::new(io::ErrorKind::InvalidData, e);
+    let mut errors = vec![];
+    // The error being parsed and the local names of the open elements inside it.
+    let mut current: Option<RpcError> = None;
+    let mut path: Vec<String> = vec![];
+    let mut text = String::new();
     loop {
-        // Reading 1 byte at a time is inefficient; this is for example purposes only.
-        let mut buffer = [1u8; 1];
-        let bytes_read = channel.read(&mut buffer[..])?;
-        let s = String::from_utf8_lossy(&buffer[..bytes_read]);
-        result.push_str(&s);
-        if result.ends_with("]]>]]>") {
-            break;
-        }
-        if result.ends_with("##") {
-            break;
-        }
-        if bytes_read == 0 || channel.eof() {
-            println!("Buffer is empty, SSH channel read terminated");
-            break;
+        match reader.read_event().map_err(invalid)? {
+            Event::Start(ref e) => {
+                let name = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                if name == "rpc-error" {
+                    current = Some(RpcError::default());
+                    path.clear();
+                } else if current.is_some() {
+                    path.push(name);
+                }
+                text.clear();
+            }
+            Event::Empty(ref e) => {
+                if let (Some(error), [info]) = (current.as_mut(), path.as_slice()) {
+                    if info == "error-info" {
+                        let name = String::from_utf8_lossy(e.local_name().as_ref()).to_string();
+                        error.error_info.insert(name, String::new());
+                    }
+                }
+            }
+            Event::Text(ref e) if current.is_some() => {
+                text.push_str(&e.unescape().map_err(invalid)?);
+            }
+            Event::End(ref e) => {
+                if e.local_name().as_ref() == b"rpc-error" {
+                    errors.extend(current.take());
+                } else if let Some(error) = current.as_mut() {
+                    let value = Some(text.trim().to_string());
+                    match path.as_slice() {
+                        [field] => match field.as_str() {
+                            "error-type" => error.error_type = value,
+                            "error-tag" => error.error_tag = value,
+                            "error-severity" => error.error_severity = value,
+                            "error-app-tag" => error.error_app_tag = value,
+                            "error-path" => error.error_path = value,
+                            "error-message" => error.error_message = value,
+                            _ => {}
+                        },
+                        [info, child] if info == "error-info" => {
+                            error.error_info.insert(child.clone(), text.trim().to_string());
+                        }
+                        _ => {}
+                    }
+                    path.pop();
+                }
+                text.clear();
+            }
+            Event::Eof => break,
+            _ => {}
         }
     }
-    Ok(result)
+    Ok(errors)
+}
+
+/// Wraps a NETCONF operation in an `<rpc>` element with the given message-id.
+fn wrap_rpc(message_id: u64, operation: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rpc message-id=\"{}\" xmlns=\"urn:ietf:params:xml:ns:netconf:base:1.0\">\n{}\n</rpc>",
+        message_id,
+        operation.trim()
+    )
 }
 
-/// Establishes an SSH connection to a host and sends a NETCONF payload.
+/// Authenticates an SSH session trying each configured method in order.
 ///
 /// # Arguments
 ///
-/// * `host` - The hostname or IP address of the target device.
-/// * `port` - The port number for the SSH connection.
+/// * `sess` - The SSH session, after the handshake.
 /// * `username` - The username for SSH authentication.
-/// * `password` - The password for SSH authentication.
-/// * `payload` - The NETCONF payload to be sent.
+/// * `methods` - The authentication methods to try.
 ///
 /// # Returns
 ///
-/// A `Result` containing the response from the device as a `String` or an `Error` if the operation fails.
-pub fn get(
-    host: &str,
-    port: isize,
-    username: &str,
-    password: &str,
-    payload: &str,
-) -> Result<String, Box<dyn Error>> {
-    let tcp = TcpStream::connect(format!("{}:{}", host, port))?;
-    let mut sess = Session::new()?;
-    sess.set_tcp_stream(tcp);
-    sess.handshake().unwrap();
-    sess.userauth_password(username, password)?;
-    let mut channel = sess.channel_session()?;
-    channel.subsystem("netconf")?;
-    let _ = read(&mut channel)?;
-    println!("Connected");
-
-    let payload = format!("{}\n#{}\n{}\n##\n", HELLO, payload.len(), payload);
-    let _ = channel.write(payload.as_bytes())?;
-    let result = read(&mut channel)?;
-
-    channel.send_eof()?;
-    channel.wait_eof()?;
-    channel.close()?;
-    channel.wait_close()?;
-    Ok(result)
+/// A `Result` which is an `Error` listing every failed attempt if no method succeeds.
+fn authenticate(sess: &Session, username: &str, methods: &[AuthMethod]) -> Result<(), Box<dyn Error>> {
+    let mut failures = vec![];
+    for method in methods {
+        let (name, result) = match method {
+            AuthMethod::PrivateKey { key: PrivateKey::Inline(pem), passphrase } => (
+                "publickey",
+                sess.userauth_pubkey_memory(username, None, pem, passphrase.as_deref()),
+            ),
+            AuthMethod::PrivateKey { key: PrivateKey::File(path), passphrase } => (
+                "publickey",
+                sess.userauth_pubkey_file(username, None, path, passphrase.as_deref()),
+            ),
+            AuthMethod::Agent => ("agent", authenticate_agent(sess, username)),
+            AuthMethod::Password(password) => ("password", sess.userauth_password(username, password)),
+        };
+        match result {
+            Ok(()) if sess.authenticated() => return Ok(()),
+            Ok(()) => failures.push(format!("{}: not authenticated", name)),
+            Err(e) => failures.push(format!("{}: {}", name, e)),
+        }
+    }
+    if failures.is_empty() {
+        return Err("no authentication method configured".into());
+    }
+    Err(format!("authentication failed for {} ({})", username, failures.join("; ")).into())
+}
+
+/// Authenticates an SSH session with the identities held by the local ssh-agent.
+///
+/// Unlike `Session::userauth_agent`, every identity is tried, not only the first one.
+fn authenticate_agent(sess: &Session, username: &str) -> Result<(), ssh2::Error> {
+    let mut agent = sess.agent()?;
+    agent.connect()?;
+    agent.list_identities()?;
+    let mut result = Err(ssh2::Error::new(
+        ssh2::ErrorCode::Session(LIBSSH2_ERROR_INVAL),
+        "no identities found in the ssh agent",
+    ));
+    for identity in agent.identities()? {
+        result = agent.userauth(username, &identity);
+        if result.is_ok() {
+            break;
+        }
+    }
+    let _ = agent.disconnect();
+    result
+}
+
+/// An open NETCONF session over SSH, ready to exchange RPCs.
+pub struct NetconfSession {
+    /// The SSH session carrying the netconf subsystem channel.
+    session: Session,
+    /// The framing layer over the netconf subsystem channel.
+    framer: Framer<Channel>,
+    /// The hello received from the server.
+    hello: ServerHello,
+    /// The message-id of the next RPC.
+    next_message_id: u64,
+    /// Time allowed to receive the complete reply of an RPC.
+    rpc_timeout: Duration,
+}
+
+impl NetconfSession {
+    /// Establishes an SSH connection to a host, verifies its host key, opens the
+    /// netconf subsystem and performs the hello exchange.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The hostname or IP address of the target device.
+    /// * `parameters` - The connection parameters of the host.
+    /// * `known_hosts` - The store of trusted host keys.
+    /// * `default_timeouts` - The timeouts used when the host does not set its own.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the open session or an `Error` if the connection fails.
+    /// A `TimeoutError` is returned if a stage exceeds its timeout.
+    pub fn connect(
+        host: &str,
+        parameters: &HostParameters,
+        known_hosts: &KnownHosts,
+        default_timeouts: &Timeouts,
+    ) -> Result<Self, Box<dyn Error>> {
+        let timeouts = parameters.timeouts.or(default_timeouts);
+        let tcp = connect_tcp(host, parameters.port, timeouts.connect())
+            .map_err(|e| into_timeout(e, "connect", timeouts.connect()))?;
+        let mut sess = Session::new()?;
+        sess.set_tcp_stream(tcp);
+        sess.set_timeout(as_millis(timeouts.handshake()));
+        let (framer, hello) = Self::open(host, &mut sess, parameters, known_hosts, timeouts.handshake())
+            .map_err(|e| into_timeout(e, "handshake", timeouts.handshake()))?;
+
+        Ok(NetconfSession {
+            session: sess,
+            framer,
+            hello,
+            next_message_id: 101,
+            rpc_timeout: timeouts.rpc(),
+        })
+    }
+
+    /// Performs the SSH handshake, authentication and hello exchange on a connected session.
+    fn open(
+        host: &str,
+        sess: &mut Session,
+        parameters: &HostParameters,
+        known_hosts: &KnownHosts,
+        timeout: Duration,
+    ) -> Result<(Framer<Channel>, ServerHello), Box<dyn Error>> {
+        let deadline = Instant::now() + timeout;
+        sess.handshake()?;
+        // Never send credentials before the host key is trusted.
+        known_hosts.verify(host, sess)?;
+        // Keepalives are only sent when `is_alive` checks an idle session.
+        sess.set_keepalive(false, KEEPALIVE_INTERVAL);
+        authenticate(sess, &parameters.user, &parameters.auth_methods)?;
+        let mut channel = sess.channel_session()?;
+        channel.subsystem("netconf")?;
+
+        let mut framer = Framer::new(channel);
+        framer.set_deadline(Some(deadline));
+        // The hello exchange always uses end-of-message framing.
+        let hello = ServerHello::parse(&framer.read_eom()?)?;
+        framer.write_eom(HELLO)?;
+        framer.set_framing(hello.framing());
+        Ok((framer, hello))
+    }
+
+    /// Returns the capabilities and session-id announced by the server.
+    pub fn hello(&self) -> &ServerHello {
+        &self.hello
+    }
+
+    /// Sends a NETCONF operation and waits for its reply.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The operation to send, e.g. a `<get>` element. It is wrapped
+    ///   in an `<rpc>` element carrying the next message-id.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `<rpc-reply>` as a `String` or an `Error` if the exchange fails.
+    /// A `TimeoutError` is returned if the reply is not received within the RPC timeout, and
+    /// `RpcErrors` if the device answers with an `<rpc-error>` of severity `error`.
+    pub fn rpc(&mut self, operation: &str) -> Result<String, Box<dyn Error>> {
+        let message_id = self.next_message_id;
+        self.next_message_id += 1;
+
+        let timeout = self.rpc_timeout;
+        self.session.set_timeout(as_millis(timeout));
+        self.framer.set_deadline(Some(Instant::now() + timeout));
+        let reply = self
+            .framer
+            .write_message(&wrap_rpc(message_id, operation))
+            .and_then(|_| self.framer.read_message())
+            .map_err(|e| into_timeout(e.into(), "rpc", timeout))?;
+
+        // Only parse replies that may contain errors, data replies can be several megabytes.
+        // Warnings alone do not fail the RPC and are returned with the reply.
+        if reply.contains("rpc-error") {
+            let errors = parse_rpc_errors(&reply)?;
+            if errors.iter().any(|e| e.is_error()) {
+                return Err(Box::new(RpcErrors { errors }));
+            }
+        }
+        Ok(reply)
+    }
+
+    /// Checks whether the session can still be used.
+    ///
+    /// The channel must not have reached EOF and a keepalive must be sent successfully.
+    pub fn is_alive(&self) -> bool {
+        !self.framer.get_ref().eof() && self.session.keepalive_send().is_ok()
+    }
+
+    /// Closes the session with `<close-session>` and shuts the channel down.
+    pub fn close(mut self) -> Result<(), Box<dyn Error>> {
+        let _ = self.rpc("<close-session/>")?;
+        let mut channel = self.framer.into_inner();
+        channel.send_eof()?;
+        channel.wait_eof()?;
+        channel.close()?;
+        channel.wait_close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// In-memory stream returning at most `step` bytes per read and recording writes.
+    struct MemoryStream {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+        step: usize,
+    }
+
+    impl MemoryStream {
+        fn new(input: &[u8], step: usize) -> Self {
+            MemoryStream { input: io::Cursor::new(input.to_vec()), output: vec![], step }
+        }
+    }
+
+    impl Read for MemoryStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let length = buf.len().min(self.step);
+            self.input.read(&mut buf[..length])
+        }
+    }
+
+    impl Write for MemoryStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn framer_over(input: &[u8], step: usize) -> Framer<MemoryStream> {
+        Framer::new(MemoryStream::new(input, step))
+    }
+
+    #[test]
+    fn reads_eom_messages_split_across_reads() {
+        for step in [1, 2, 5, READ_BLOCK_SIZE] {
+            let mut framer = framer_over(b"<hello/>]]>]]><rpc-reply/>]]>]]>", step);
+            assert_eq!(framer.read_eom().unwrap(), "<hello/>");
+            assert_eq!(framer.read_eom().unwrap(), "<rpc-reply/>");
+            assert_eq!(framer.read_eom().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn keeps_partial_delimiters_in_eom_messages() {
+        let mut framer = framer_over(b"a]]>]]b]]>]]>", 3);
+        assert_eq!(framer.read_eom().unwrap(), "a]]>]]b");
+    }
+
+    #[test]
+    fn reads_chunked_messages_split_across_reads() {
+        for step in [1, 3, READ_BLOCK_SIZE] {
+            let mut framer = framer_over(b"\n#4\n<rpc\n#8\n-reply/>\n##\n\n#3\n<a/\n#1\n>\n##\n", step);
+            framer.set_framing(Framing::Chunked);
+            assert_eq!(framer.read_message().unwrap(), "<rpc-reply/>");
+            assert_eq!(framer.read_message().unwrap(), "<a/>");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_chunk_headers() {
+        for input in [&b"\n##\n"[..], b"\n#0\n", b"\n#01\na", b"\n#1x\na\n##\n", b"#1\na\n##\n"] {
+            let mut framer = framer_over(input, READ_BLOCK_SIZE);
+            assert_eq!(framer.read_chunked().unwrap_err().kind(), io::ErrorKind::InvalidData, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_chunks() {
+        let mut framer = framer_over(b"\n#4294967296\n", READ_BLOCK_SIZE);
+        let error = framer.read_chunked().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("RFC 6242 limit"));
+    }
+
+    #[test]
+    fn fails_on_truncated_chunks() {
+        let mut framer = framer_over(b"\n#10\nshort", 2);
+        assert_eq!(framer.read_chunked().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn times_out_after_the_deadline() {
+        let mut framer = framer_over(b"<rpc-reply/>]]>]]>", READ_BLOCK_SIZE);
+        framer.set_deadline(Some(Instant::now()));
+        assert_eq!(framer.read_eom().unwrap_err().kind(), io::ErrorKind::TimedOut);
+
+        // Messages already buffered are returned even once the deadline has passed.
+        let mut framer = framer_over(b"<a/>]]>]]><b/>]]>]]>", READ_BLOCK_SIZE);
+        assert_eq!(framer.read_eom().unwrap(), "<a/>");
+        framer.set_deadline(Some(Instant::now()));
+        assert_eq!(framer.read_eom().unwrap(), "<b/>");
+        assert_eq!(framer.read_eom().unwrap_err().kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn writes_messages_with_the_current_framing() {
+        let mut framer = framer_over(b"", READ_BLOCK_SIZE);
+        framer.write_message("<hello/>").unwrap();
+        framer.set_framing(Framing::Chunked);
+        framer.write_message("<rpc/>").unwrap();
+        assert_eq!(framer.into_inner().output, b"<hello/>]]>]]>\n#6\n<rpc/>\n##\n");
+    }
+
+    #[test]
+    fn refuses_end_of_message_markers_in_eom_messages() {
+        let mut framer = framer_over(b"", READ_BLOCK_SIZE);
+        let error = framer.write_message("<get/>]]>]]><close-session/>").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(framer.write_message("<get a=\"]]>]]\"/>").is_ok());
+        // Chunked framing delimits messages by their size.
+        framer.set_framing(Framing::Chunked);
+        assert!(framer.write_message("<get a=\"]]>]]>\"/>").is_ok());
+        assert!(!holds_end_of_message("<get/>]]>]]"));
+    }
+
+    #[test]
+    fn parses_the_server_hello() {
+        let hello = ServerHello::parse(
+            "<hello xmlns=\"urn:ietf:params:xml:ns:netconf:base:1.0\"><capabilities>\
+             <capability>urn:ietf:params:netconf:base:1.0</capability>\
+             <capability>urn:ietf:params:netconf:capability:with-defaults:1.0?basic-mode=explicit</capability>\
+             </capabilities><session-id>42</session-id></hello>",
+        )
+        .unwrap();
+        assert_eq!(hello.session_id, Some(42));
+        assert_eq!(hello.framing(), Framing::EndOfMessage);
+        let with_defaults = "urn:ietf:params:netconf:capability:with-defaults:1.0";
+        assert!(hello.supports(with_defaults));
+        assert_eq!(hello.parameter(with_defaults, "basic-mode"), Some("explicit"));
+    }
+
+    #[test]
+    fn rejects_server_hellos_without_base_capability_or_session_id() {
+        let base = "<capabilities><capability>urn:ietf:params:netconf:base:1.1</capability></capabilities>";
+        assert!(ServerHello::parse(&format!("<hello>{}<session-id>1</session-id></hello>", base)).is_ok());
+        assert!(ServerHello::parse(&format!("<hello>{}</hello>", base)).is_err());
+        assert!(ServerHello::parse("<hello><capabilities/><session-id>1</session-id></hello>").is_err());
+        assert!(ServerHello::parse(&format!("<hello>{}<session-id>x</session-id></hello>", base)).is_err());
+    }
 }
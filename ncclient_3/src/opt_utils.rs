@@ -1,7 +1,11 @@
-use prometheus::{GaugeVec, Registry};
+use prometheus::{CounterVec, GaugeVec, Registry};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 use crate::AppState;
-use crate::models::{ComponentMetrics, ChannelMetrics, AdvanceMetric};
+use crate::metric_config::{MetricDefinition, MetricKind, MetricsConfig};
+use crate::models::{ConfiguredMetric, MetricVec, PollMetrics, SeriesTimes, TrackedGauge};
+use crate::tags::with_tags;
 
 /// Creates a `GaugeVec` metric and registers it with the provided registry.
 ///
@@ -9,189 +13,150 @@ use crate::models::{ComponentMetrics, ChannelMetrics, AdvanceMetric};
 ///
 /// * `name` - The name of the metric.
 /// * `help` - A help description of the metric.
-/// * `labelnames` - A string representing the label names for the metric.
+/// * `labelnames` - The label names of the metric.
 /// * `registry` - The `Registry` instance to register the metric.
 ///
 /// # Returns
 ///
 /// A `GaugeVec` metric instance.
-fn return_opt(name: &str, help: &str, labelnames: &str, registry: Registry) -> GaugeVec {
+fn return_opt(name: &str, help: &str, labelnames: &[&str], registry: Registry) -> GaugeVec {
     let state = GaugeVec::new(
         prometheus::Opts::new(name, help),
-        &[labelnames],
+        labelnames,
     )
     .unwrap();
     registry.register(Box::new(state.clone())).unwrap();
     state
 }
 
+/// Creates a `CounterVec` metric and registers it with the provided registry.
+///
+/// # Arguments
+///
+/// * `name` - The name of the metric.
+/// * `help` - A help description of the metric.
+/// * `labelnames` - The label names of the metric.
+/// * `registry` - The `Registry` instance to register the metric.
+///
+/// # Returns
+///
+/// A `CounterVec` metric instance.
+fn return_counter(name: &str, help: &str, labelnames: &[&str], registry: Registry) -> CounterVec {
+    let counter = CounterVec::new(prometheus::Opts::new(name, help), labelnames).unwrap();
+    registry.register(Box::new(counter.clone())).unwrap();
+    counter
+}
+
+/// Creates the Prometheus collector of a metric definition and registers it with the provided registry.
+///
+/// # Arguments
+///
+/// * `definition` - The metric definition.
+/// * `tag_labels` - The host tags added as labels.
+/// * `registry` - The `Registry` instance to register the metric.
+///
+/// # Returns
+///
+/// A `prometheus::Result` containing the metric, or an error if the name or labels are invalid
+/// or the name is already registered.
+fn return_configured(
+    definition: &MetricDefinition,
+    tag_labels: &[String],
+    registry: Registry,
+) -> prometheus::Result<ConfiguredMetric> {
+    let opts = prometheus::Opts::new(definition.name.clone(), definition.help.clone());
+    let label_names = with_tags(&definition.label_names(), tag_labels);
+    let metric = match definition.kind {
+        MetricKind::Gauge | MetricKind::Info => {
+            let gauge = GaugeVec::new(opts, &label_names)?;
+            registry.register(Box::new(gauge.clone()))?;
+            MetricVec::Gauge(gauge)
+        }
+        MetricKind::Counter => {
+            let counter = CounterVec::new(opts, &label_names)?;
+            registry.register(Box::new(counter.clone()))?;
+            MetricVec::Counter(counter)
+        }
+    };
+    Ok(ConfiguredMetric {
+        definition: definition.clone(),
+        metric,
+        times: SeriesTimes::default(),
+    })
+}
+
 /// Registers and initializes metrics for the application.
 ///
 /// # Arguments
 ///
 /// * `registry` - The `Registry` instance to register the metrics.
+/// * `config` - The metric definitions loaded from the definition file. Its tag labels are
+///   added to every metric.
 ///
 /// # Returns
 ///
-/// An `AppState` instance with initialized metrics.
-pub fn register_init_metric(registry: Registry) -> AppState {
-    AppState {
-        optic_data: ComponentMetrics {
-            channel: ChannelMetrics {
-                input_power: AdvanceMetric {
-                    avg: return_opt(
-                        "input_power_avg",
-                        "Input Power Average",
-                        "input_power_avg",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    instant: return_opt(
-                        "input_power_instant",
-                        "Input Power Instant",
-                        "input_power_instant",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    interval: return_opt(
-                        "input_power_interval",
-                        "Input Power Interval",
-                        "input_power_interval",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    max: return_opt(
-                        "input_power_max",
-                        "Input Power Max",
-                        "input_power_max",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    max_time: return_opt(
-                        "input_power_max_time",
-                        "Input Power Max Time",
-                        "input_power_max_time",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    min: return_opt(
-                        "input_power_min",
-                        "Input Power Min",
-                        "input_power_min",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    min_time: return_opt(
-                        "input_power_min_time",
-                        "Input Power Min Time",
-                        "input_power_min_time",
-                        registry.clone(),
-                    )
-                    .clone(),
-                },
-                laser_bias_current: AdvanceMetric {
-                    avg: return_opt(
-                        "laser_bias_current_avg",
-                        "Laser Bias Current Average",
-                        "laser_bias_current_avg",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    instant: return_opt(
-                        "laser_bias_current_instant",
-                        "Laser Bias Current Instant",
-                        "laser_bias_current_instant",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    interval: return_opt(
-                        "laser_bias_current_interval",
-                        "Laser Bias Current Interval",
-                        "laser_bias_current_interval",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    max: return_opt(
-                        "laser_bias_current_max",
-                        "Laser Bias Current Max",
-                        "laser_bias_current_max",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    max_time: return_opt(
-                        "laser_bias_current_max_time",
-                        "Laser Bias Current Max Time",
-                        "laser_bias_current_max_time",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    min: return_opt(
-                        "laser_bias_current_min",
-                        "Laser Bias Current Min",
-                        "laser_bias_current_min",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    min_time: return_opt(
-                        "laser_bias_current_min_time",
-                        "Laser Bias Current Min Time",
-                        "laser_bias_current_min_time",
-                        registry.clone(),
-                    )
-                    .clone(),
-                },
-                output_power: AdvanceMetric {
-                    avg: return_opt(
-                        "output_power_avg",
-                        "Output Power Average",
-                        "output_power_avg",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    instant: return_opt(
-                        "output_power_instant",
-                        "Output Power Instant",
-                        "output_power_instant",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    interval: return_opt(
-                        "output_power_interval",
-                        "Output Power Interval",
-                        "output_power_interval",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    max: return_opt(
-                        "output_power_max",
-                        "Output Power Max",
-                        "output_power_max",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    max_time: return_opt(
-                        "output_power_max_time",
-                        "Output Power Max Time",
-                        "output_power_max_time",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    min: return_opt(
-                        "output_power_min",
-                        "Output Power Min",
-                        "output_power_min",
-                        registry.clone(),
-                    )
-                    .clone(),
-                    min_time: return_opt(
-                        "output_power_min_time",
-                        "Output Power Min Time",
-                        "output_power_min_time",
-                        registry.clone(),
-                    )
-                    .clone(),
-                },
-            },
+/// A `prometheus::Result` containing an `AppState` instance with initialized metrics, or an
+/// error if a configured metric cannot be registered.
+pub fn register_init_metric(registry: Registry, config: &MetricsConfig) -> prometheus::Result<AppState> {
+    let metrics = config
+        .metrics
+        .iter()
+        .map(|definition| return_configured(definition, &config.tag_labels, registry.clone()))
+        .collect::<prometheus::Result<Vec<_>>>()?;
+
+    Ok(AppState {
+        metrics: Arc::new(metrics),
+        poll_status: Arc::new(Mutex::new(HashMap::new())),
+        inventory: Arc::new(Mutex::new(HashMap::new())),
+        channel_status: Arc::new(Mutex::new(HashMap::new())),
+        channel_status_metric: TrackedGauge {
+            gauge: return_opt(
+                "transceiver_channel_status",
+                "Status of the channel compared with the transceiver thresholds: 0 ok, 1 warning, 2 alarm",
+                &with_tags(&["host", "component", "channel", "quantity"], &config.tag_labels),
+                registry.clone(),
+            ),
+            times: SeriesTimes::default(),
+        },
+        tag_labels: Arc::new(config.tag_labels.clone()),
+        series_tags: Arc::new(Mutex::new(HashMap::new())),
+        poll_metrics: PollMetrics {
+            up: return_opt(
+                "netconf_up",
+                "Whether the last collection of the host succeeded",
+                &with_tags(&["host"], &config.tag_labels),
+                registry.clone(),
+            ),
+            scrape_duration: return_opt(
+                "netconf_scrape_duration_seconds",
+                "Duration of the last NETCONF RPC sent to the host, by operation",
+                &with_tags(&["host", "rpc"], &config.tag_labels),
+                registry.clone(),
+            ),
+            scrape_errors: return_counter(
+                "netconf_scrape_errors_total",
+                "Number of failed collections of the host, by reason",
+                &with_tags(&["host", "reason"], &config.tag_labels),
+                registry.clone(),
+            ),
+            reply_bytes: return_opt(
+                "netconf_reply_bytes",
+                "Size in bytes of the last NETCONF reply of the host",
+                &with_tags(&["host"], &config.tag_labels),
+                registry.clone(),
+            ),
+            last_success: return_opt(
+                "netconf_last_success_timestamp",
+                "Unix timestamp of the last successful collection of the host",
+                &with_tags(&["host"], &config.tag_labels),
+                registry.clone(),
+            ),
+            last_error: return_opt(
+                "netconf_last_error_timestamp",
+                "Unix timestamp of the last failed collection of the host",
+                &with_tags(&["host"], &config.tag_labels),
+                registry.clone(),
+            ),
         },
-    }
+    })
 }
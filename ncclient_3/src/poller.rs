@@ -0,0 +1,154 @@
+use actix_web::rt::time::sleep;
+use actix_web::web;
+use rand::Rng;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+use crate::collector::{collect, expire_series};
+use crate::session::SessionPool;
+use crate::{AppState, HostParameters};
+
+/// Default seconds between two collections of a host, overridden by `NETCONF_POLL_INTERVAL`.
+const DEFAULT_POLL_INTERVAL: u64 = 60;
+
+/// Default number of hosts collected at the same time, overridden by `NETCONF_POLL_CONCURRENCY`.
+const DEFAULT_POLL_CONCURRENCY: usize = 8;
+
+/// Default seconds after which a series that is not updated is removed, overridden by `NETCONF_METRIC_TTL`.
+const DEFAULT_METRIC_TTL: u64 = 600;
+
+/// Number of polling intervals a series is kept without update, for hosts polled less
+/// often than the metric TTL.
+const TTL_POLL_INTERVALS: u32 = 3;
+
+/// Fraction of the interval added to or removed from every delay, spreading the load.
+const JITTER: f64 = 0.1;
+
+/// How often the scheduler looks for hosts that are due.
+const TICK: Duration = Duration::from_secs(1);
+
+/// Returns the maximum number of hosts collected at the same time, from the
+/// `NETCONF_POLL_CONCURRENCY` environment variable.
+pub fn concurrency_from_env() -> usize {
+    env::var("NETCONF_POLL_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_POLL_CONCURRENCY)
+        .max(1)
+}
+
+/// Background scheduler collecting every registered host on its own interval.
+pub struct Poller {
+    /// The registered hosts.
+    host_dictionary: Arc<Mutex<HashMap<String, HostParameters>>>,
+    /// The persistent NETCONF sessions.
+    session_pool: web::Data<SessionPool>,
+    /// The application state holding the metrics.
+    state: AppState,
+    /// Interval used by hosts that do not set their own. Zero disables polling.
+    default_interval: Duration,
+    /// Maximum number of hosts collected at the same time.
+    max_concurrent: usize,
+    /// Time after which a series that is not updated is removed, or `TTL_POLL_INTERVALS`
+    /// polling intervals of its host if longer. Zero keeps series forever.
+    metric_ttl: Duration,
+}
+
+impl Poller {
+    /// Creates a scheduler using the `NETCONF_POLL_INTERVAL` (seconds, `0` disables polling
+    /// of hosts without their own interval), `NETCONF_POLL_CONCURRENCY` and `NETCONF_METRIC_TTL`
+    /// (seconds, `0` keeps series forever) environment variables.
+    pub fn from_env(
+        host_dictionary: Arc<Mutex<HashMap<String, HostParameters>>>,
+        session_pool: web::Data<SessionPool>,
+        state: AppState,
+    ) -> Self {
+        let default_interval = env::var("NETCONF_POLL_INTERVAL")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_POLL_INTERVAL);
+        let metric_ttl = env::var("NETCONF_METRIC_TTL")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_METRIC_TTL);
+        Poller {
+            host_dictionary,
+            session_pool,
+            state,
+            default_interval: Duration::from_secs(default_interval),
+            max_concurrent: concurrency_from_env(),
+            metric_ttl: Duration::from_secs(metric_ttl),
+        }
+    }
+
+    /// Runs the scheduler forever.
+    ///
+    /// The first collection of a host happens after a random delay within its interval,
+    /// and every following delay is the interval with a jitter of `JITTER`. A host is
+    /// never collected twice at the same time. Series not updated for the metric TTL, such
+    /// as those of removed or unreachable hosts, are removed. Hosts polled less often keep
+    /// their series for `TTL_POLL_INTERVALS` of their intervals, so they do not expire
+    /// between two polls.
+    pub async fn run(self) {
+        let semaphore = Arc::new(Semaphore::new(self.max_concurrent));
+        let in_flight: Arc<Mutex<HashSet<String>>> = Arc::new(Mutex::new(HashSet::new()));
+        let mut next_poll: HashMap<String, Instant> = HashMap::new();
+
+        loop {
+            let hosts: Vec<(String, HostParameters)> = self
+                .host_dictionary
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(host, parameters)| (host.clone(), parameters.clone()))
+                .collect();
+            // Forget the schedule of removed hosts.
+            next_poll.retain(|host, _| hosts.iter().any(|(h, _)| h == host));
+
+            let now = Instant::now();
+            let mut ttls: HashMap<String, Duration> = HashMap::new();
+            for (host, parameters) in hosts {
+                let interval = parameters
+                    .poll_interval
+                    .map(Duration::from_secs)
+                    .unwrap_or(self.default_interval);
+                if interval.is_zero() {
+                    continue;
+                }
+                ttls.insert(host.clone(), self.metric_ttl.max(interval * TTL_POLL_INTERVALS));
+                let due = next_poll
+                    .entry(host.clone())
+                    .or_insert_with(|| now + interval.mul_f64(rand::thread_rng().gen_range(0.0..1.0)));
+                if *due > now || !in_flight.lock().unwrap().insert(host.clone()) {
+                    continue;
+                }
+                *due = now + interval.mul_f64(rand::thread_rng().gen_range(1.0 - JITTER..1.0 + JITTER));
+
+                let semaphore = semaphore.clone();
+                let in_flight = in_flight.clone();
+                let host_dictionary = self.host_dictionary.clone();
+                let session_pool = self.session_pool.clone();
+                let state = self.state.clone();
+                actix_web::rt::spawn(async move {
+                    if let Ok(_permit) = semaphore.acquire_owned().await {
+                        // Failures are recorded in the poll status of the host.
+                        let _ = collect(&host, parameters, &host_dictionary, &session_pool, &state).await;
+                    }
+                    in_flight.lock().unwrap().remove(&host);
+                });
+            }
+
+            // Remove the series that were not updated for too long.
+            if !self.metric_ttl.is_zero() {
+                expire_series(&self.state, &|host| {
+                    now.checked_sub(ttls.get(host).copied().unwrap_or(self.metric_ttl))
+                });
+            }
+
+            sleep(TICK).await;
+        }
+    }
+}
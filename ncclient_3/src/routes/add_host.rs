@@ -1,45 +1,46 @@
-use actix_web::{post, web, Responder, HttpResponse};
+use actix_web::{post, web, HttpResponse};
 use std::collections::HashMap;
-use serde::Deserialize;
 use std::sync::{Arc, Mutex};
 
+use crate::credentials::CredentialProfiles;
+use crate::errors::ApiError;
+use crate::host_store::HostStore;
+use crate::routes::hosts::{store_host, CreateHostRequest};
 use crate::HostParameters;
 
-/// Struct representing the request body for adding a new host.
-#[derive(Debug, Clone, Deserialize)]
-struct AddHostRequest {
-    host: String,
-    port: isize,
-    user: String,
-    password: String,
-}
-
-/// HTTP POST endpoint to add a new host to the host dictionary.
+/// HTTP POST endpoint to add a new host to the host dictionary, replacing an existing one.
+/// 
+/// Kept for compatibility, `POST /hosts` refuses to replace existing hosts.
 /// 
 /// # Arguments
 /// 
 /// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
-/// * `request` - A `web::Json<AddHostRequest>` representing the request body containing host details.
+/// * `host_store` - A `web::Data<HostStore>` persisting the host dictionary.
+/// * `profiles` - A `web::Data<CredentialProfiles>` holding the credential profiles.
+/// * `request` - A `web::Json<CreateHostRequest>` representing the request body containing host details.
 /// 
 /// # Returns
 /// 
-/// An `impl Responder` containing an `HttpResponse` indicating the result of the operation.
+/// A `Result` containing an `HttpResponse` indicating the result of the operation, or an
+/// `ApiError` if the request is invalid or the host cannot be stored.
 #[post("/add_host")]
 pub async fn add_host(
     host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>, 
-    request: web::Json<AddHostRequest>
-) -> impl Responder {
-    // Lock the host dictionary for writing.
-    let mut host_dictionary = host_dictionary.lock().unwrap();
-    // Create a new HostParameters instance from the request data.
-    let host_parameters = HostParameters {
-        port: request.port.clone(),
-        user: request.user.clone(),
-        password: request.password.clone(),
-    };
-    // Insert the new host into the dictionary.
-    host_dictionary.insert(request.host.clone(), host_parameters);
+    host_store: web::Data<HostStore>,
+    profiles: web::Data<CredentialProfiles>,
+    request: web::Json<CreateHostRequest>
+) -> Result<HttpResponse, ApiError> {
+    let CreateHostRequest { host, settings } = request.into_inner();
+    // Validate the settings before touching the dictionary.
+    let host_parameters = settings.into_parameters(&host).await?;
+    // Write the host through to the store, then insert it into the dictionary, on the
+    // blocking thread pool.
+    let added = host.clone();
+    web::block(move || {
+        let mut host_dictionary = host_dictionary.lock().unwrap();
+        store_host(&mut host_dictionary, host_store.get_ref(), &profiles, &added, host_parameters)
+    })
+    .await??;
     // Return an HTTP response indicating successful addition.
-    HttpResponse::Ok().message_body(format!("{} added successfully", request.host.clone()))
+    Ok(HttpResponse::Ok().body(format!("{} added successfully", host)))
 }
-
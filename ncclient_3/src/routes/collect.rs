@@ -0,0 +1,113 @@
+use actix_web::{get, web, HttpResponse};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+
+use crate::collector::collect;
+use crate::errors::ApiError;
+use crate::poller::concurrency_from_env;
+use crate::routes::hosts::SelectorQuery;
+use crate::session::SessionPool;
+use crate::{AppState, HostParameters};
+
+/// Outcome of the collection of a host.
+#[derive(Debug, Clone, Serialize)]
+struct HostResult {
+    /// The hostname or IP address of the device.
+    host: String,
+    /// The data of every component, like `/get_json/{host}`, if the collection succeeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    components: Option<Vec<HashMap<String, Value>>>,
+    /// The kind of error, like the `error` of error responses, if the collection failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+    /// The description of the error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+/// Outcome of the collection of every selected host.
+#[derive(Debug, Clone, Serialize)]
+struct CollectReport {
+    /// The number of hosts collected successfully.
+    succeeded: usize,
+    /// The number of hosts whose collection failed.
+    failed: usize,
+    /// The result of every host, sorted by host.
+    results: Vec<HostResult>,
+}
+
+/// HTTP GET endpoint to collect every host matching a tag or group selector, like `/get_json/{host}`.
+///
+/// The hosts are collected concurrently, at most `NETCONF_POLL_CONCURRENCY` at a time.
+///
+/// # Arguments
+///
+/// * `query` - A `web::Query<SelectorQuery>` selecting the hosts by tags and group.
+/// * `state` - A `web::Data<AppState>` representing the application state.
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+/// * `session_pool` - A `web::Data<SessionPool>` holding the persistent NETCONF sessions.
+///
+/// # Returns
+///
+/// A `Result` containing the data or error of every selected host, or an `ApiError` if the
+/// selector is invalid or empty.
+#[get("/collect")]
+pub async fn collect_hosts(
+    query: web::Query<SelectorQuery>,
+    state: web::Data<AppState>,
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+    session_pool: web::Data<SessionPool>,
+) -> Result<HttpResponse, ApiError> {
+    let selector = query.selector()?;
+    if selector.is_empty() {
+        return Err(ApiError::BadRequest("tag or group is required".to_string()));
+    }
+    // Select the hosts, releasing the lock before any network call.
+    let mut hosts: Vec<(String, HostParameters)> = host_dictionary
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, parameters)| selector.matches(parameters))
+        .map(|(host, parameters)| (host.clone(), parameters.clone()))
+        .collect();
+    hosts.sort_by(|a, b| a.0.cmp(&b.0));
+
+    // Collect the hosts concurrently, updating the metrics.
+    let semaphore = Arc::new(Semaphore::new(concurrency_from_env()));
+    let tasks: Vec<_> = hosts
+        .into_iter()
+        .map(|(host, parameters)| {
+            let semaphore = semaphore.clone();
+            let host_dictionary = host_dictionary.clone();
+            let session_pool = session_pool.clone();
+            let state = state.clone();
+            let target = host.clone();
+            let task = actix_web::rt::spawn(async move {
+                let _permit = semaphore.acquire_owned().await;
+                collect(&target, parameters, &host_dictionary, &session_pool, &state).await
+            });
+            (host, task)
+        })
+        .collect();
+
+    let mut report = CollectReport { succeeded: 0, failed: 0, results: vec![] };
+    for (host, task) in tasks {
+        let result = task
+            .await
+            .unwrap_or_else(|e| Err(ApiError::Internal(format!("collection task failed: {}", e))));
+        report.results.push(match result {
+            Ok(components) => {
+                report.succeeded += 1;
+                HostResult { host, components: Some(components), error: None, message: None }
+            }
+            Err(e) => {
+                report.failed += 1;
+                HostResult { host, components: None, error: Some(e.kind()), message: Some(e.to_string()) }
+            }
+        });
+    }
+    Ok(HttpResponse::Ok().json(report))
+}
@@ -0,0 +1,296 @@
+use actix_web::{get, web, HttpResponse};
+use quick_xml::escape::escape;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use crate::errors::ApiError;
+use crate::netconf::{holds_end_of_message, ServerHello, UnsupportedCapability, WITH_DEFAULTS, XPATH};
+use crate::routes::rpc::{reply_format, reply_response};
+use crate::session::SessionPool;
+use crate::utils::{to_xml, top_level_elements};
+use crate::HostParameters;
+
+/// Namespace of the `<with-defaults>` parameter (RFC 6243).
+const WITH_DEFAULTS_NAMESPACE: &str = "urn:ietf:params:xml:ns:yang:ietf-netconf-with-defaults";
+
+/// Retrieval modes of the `<with-defaults>` parameter.
+const WITH_DEFAULTS_MODES: [&str; 4] = ["report-all", "report-all-tagged", "trim", "explicit"];
+
+/// Struct representing the query parameters of the get endpoint.
+#[derive(Debug, Clone, Deserialize)]
+struct GetQuery {
+    /// Subtree filter, as XML or as JSON in the layout of `to_json`.
+    subtree: Option<String>,
+    /// XPath filter expression.
+    xpath: Option<String>,
+    /// Comma separated `prefix=namespace` pairs declaring the prefixes used in `xpath`.
+    namespaces: Option<String>,
+    /// Retrieval mode of default values, one of `WITH_DEFAULTS_MODES`.
+    with_defaults: Option<String>,
+    /// `json` (default) or `xml`, the format of the reply.
+    format: Option<String>,
+}
+
+/// Builds the `<filter>` element of a request, if it has a filter.
+///
+/// # Returns
+///
+/// A `Result` containing the filter and whether it is an XPath filter, or an `ApiError`
+/// if the filter is invalid.
+fn build_filter(query: &GetQuery) -> Result<Option<(String, bool)>, ApiError> {
+    match (&query.subtree, &query.xpath) {
+        (Some(_), Some(_)) => Err(ApiError::BadRequest("subtree and xpath are exclusive".to_string())),
+        (Some(subtree), None) => {
+            let subtree = if subtree.trim_start().starts_with('{') {
+                let json: Value = serde_json::from_str(subtree)
+                    .map_err(|e| ApiError::BadRequest(format!("invalid JSON subtree: {}", e)))?;
+                to_xml(&json).map_err(ApiError::BadRequest)?
+            } else {
+                subtree.clone()
+            };
+            // A malformed filter would leave the session waiting for the end of the request, and
+            // an end-of-message marker would end it early. Subtree filters may hold many elements.
+            if holds_end_of_message(&subtree) {
+                return Err(ApiError::BadRequest(
+                    "the subtree filter holds the ]]>]]> end-of-message marker".to_string(),
+                ));
+            }
+            if top_level_elements(&subtree).map_err(ApiError::BadRequest)?.is_empty() {
+                return Err(ApiError::BadRequest("the subtree filter holds no element".to_string()));
+            }
+            Ok(Some((format!("<filter type=\"subtree\">{}</filter>", subtree), false)))
+        }
+        (None, Some(xpath)) => {
+            let mut declarations = String::new();
+            for pair in query.namespaces.iter().flat_map(|namespaces| namespaces.split(',')) {
+                let (prefix, namespace) = pair
+                    .split_once('=')
+                    .map(|(prefix, namespace)| (prefix.trim(), namespace.trim()))
+                    .filter(|(prefix, namespace)| {
+                        !namespace.is_empty()
+                            && prefix.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                            && prefix.chars().all(|c| c.is_ascii_alphanumeric() || "_-.".contains(c))
+                    })
+                    .ok_or_else(|| {
+                        ApiError::BadRequest(format!("invalid namespace {:?}, expected prefix=namespace", pair))
+                    })?;
+                declarations.push_str(&format!(" xmlns:{}=\"{}\"", prefix, escape(namespace)));
+            }
+            Ok(Some((
+                format!("<filter type=\"xpath\"{} select=\"{}\"/>", declarations, escape(xpath.as_str())),
+                true,
+            )))
+        }
+        (None, None) if query.namespaces.is_some() => {
+            Err(ApiError::BadRequest("namespaces can only be used with xpath".to_string()))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+/// Builds the `<get>` operation, checking that the session supports the parameters used.
+///
+/// # Arguments
+///
+/// * `hello` - The hello of the session the operation is sent on.
+/// * `filter` - The `<filter>` element and whether it is an XPath filter.
+/// * `with_defaults` - The retrieval mode of default values.
+///
+/// # Returns
+///
+/// A `Result` containing the operation, or `UnsupportedCapability` if the server does not
+/// advertise the XPath capability or the with-defaults mode.
+fn build_get(
+    hello: &ServerHello,
+    filter: Option<&(String, bool)>,
+    with_defaults: Option<&str>,
+) -> Result<String, Box<dyn Error>> {
+    let mut operation = String::from("<get>");
+    if let Some((filter, xpath)) = filter {
+        if *xpath && !hello.supports(XPATH) {
+            return Err(Box::new(UnsupportedCapability {
+                message: "the device does not advertise the :xpath capability, use a subtree filter".to_string(),
+            }));
+        }
+        operation.push_str(filter);
+    }
+    if let Some(mode) = with_defaults {
+        // The basic mode is always supported, the others are listed in also-supported.
+        let supported: Vec<&str> = hello
+            .parameter(WITH_DEFAULTS, "basic-mode")
+            .into_iter()
+            .chain(hello.parameter(WITH_DEFAULTS, "also-supported").into_iter().flat_map(|modes| modes.split(',')))
+            .collect();
+        if !supported.contains(&mode) {
+            return Err(Box::new(UnsupportedCapability {
+                message: format!(
+                    "the device does not support the with-defaults mode {}, supported modes: {}",
+                    mode,
+                    if supported.is_empty() { "none".to_string() } else { supported.join(", ") }
+                ),
+            }));
+        }
+        operation.push_str(&format!("<with-defaults xmlns=\"{}\">{}</with-defaults>", WITH_DEFAULTS_NAMESPACE, mode));
+    }
+    operation.push_str("</get>");
+    Ok(operation)
+}
+
+/// HTTP GET endpoint to retrieve data from a host with a `<get>` and a user supplied filter.
+///
+/// # Arguments
+///
+/// * `host` - A `web::Path<String>` representing the host.
+/// * `query` - A `web::Query<GetQuery>` holding the filter, the with-defaults mode and the
+///   format of the reply.
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+/// * `session_pool` - A `web::Data<SessionPool>` holding the persistent NETCONF sessions.
+///
+/// # Returns
+///
+/// A `Result` containing the `<rpc-reply>` as XML or converted by `to_json`, or an
+/// `ApiError` if the host is unknown, the request is invalid or not supported by the
+/// device, or the device fails, times out or answers with an `<rpc-error>`.
+#[get("/hosts/{host}/get")]
+pub async fn get_data(
+    host: web::Path<String>,
+    query: web::Query<GetQuery>,
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+    session_pool: web::Data<SessionPool>,
+) -> Result<HttpResponse, ApiError> {
+    let host = host.into_inner();
+    let format = reply_format(query.format.as_deref())?;
+    let filter = build_filter(&query)?;
+    let with_defaults = query.with_defaults.clone();
+    if let Some(mode) = &with_defaults {
+        if !WITH_DEFAULTS_MODES.contains(&mode.as_str()) {
+            return Err(ApiError::BadRequest(format!(
+                "unknown with_defaults mode {:?}, expected one of {}",
+                mode,
+                WITH_DEFAULTS_MODES.join(", ")
+            )));
+        }
+    }
+    // Retrieve the host parameters, releasing the lock before any network call.
+    let parameters = host_dictionary
+        .lock()
+        .unwrap()
+        .get(&host)
+        .cloned()
+        .ok_or_else(|| ApiError::UnknownHost(host.clone()))?;
+
+    // The exchange is blocking, so it runs on the blocking thread pool instead of the worker.
+    let reply = web::block(move || -> Result<String, ApiError> {
+        Ok(session_pool.rpc_with(&host, &parameters, |hello| {
+            build_get(hello, filter.as_ref(), with_defaults.as_deref())
+        })?)
+    })
+    .await??;
+    reply_response(reply, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn query(subtree: Option<&str>, xpath: Option<&str>, namespaces: Option<&str>) -> GetQuery {
+        GetQuery {
+            subtree: subtree.map(str::to_string),
+            xpath: xpath.map(str::to_string),
+            namespaces: namespaces.map(str::to_string),
+            with_defaults: None,
+            format: None,
+        }
+    }
+
+    fn hello(capabilities: &[&str]) -> ServerHello {
+        ServerHello {
+            session_id: Some(1),
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect::<HashSet<_>>(),
+        }
+    }
+
+    fn is_unsupported(result: Result<String, Box<dyn Error>>) -> bool {
+        result.is_err_and(|e| e.downcast_ref::<UnsupportedCapability>().is_some())
+    }
+
+    #[test]
+    fn builds_subtree_filters() {
+        let filter = build_filter(&query(Some("<interfaces xmlns=\"urn:x\"/><system/>"), None, None)).unwrap();
+        assert_eq!(
+            filter,
+            Some(("<filter type=\"subtree\"><interfaces xmlns=\"urn:x\"/><system/></filter>".to_string(), false))
+        );
+        let json = r#"{"interfaces": {"@xmlns": "urn:x", "interface": null}}"#;
+        let filter = build_filter(&query(Some(json), None, None));
+        assert_eq!(
+            filter.unwrap().unwrap().0,
+            "<filter type=\"subtree\"><interfaces xmlns=\"urn:x\"><interface/></interfaces></filter>"
+        );
+        assert_eq!(build_filter(&query(None, None, None)).unwrap(), None);
+    }
+
+    #[test]
+    fn builds_xpath_filters() {
+        let filter = build_filter(&query(None, Some("/if:interfaces[name='a<b']"), Some("if=urn:x, t = urn:y")));
+        assert_eq!(
+            filter.unwrap(),
+            Some((
+                "<filter type=\"xpath\" xmlns:if=\"urn:x\" xmlns:t=\"urn:y\" select=\"/if:interfaces[name=&apos;a&lt;b&apos;]\"/>"
+                    .to_string(),
+                true
+            ))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_filters() {
+        for invalid in [
+            query(Some("<a/>"), Some("/a"), None),
+            query(Some("<a>"), None, None),
+            query(Some("text"), None, None),
+            query(Some(""), None, None),
+            query(Some("<a/>]]>]]><close-session/>"), None, None),
+            query(Some("<a>]]>]]></a>"), None, None),
+            query(Some("{\"a\": "), None, None),
+            query(Some(r#"{"a b": null}"#), None, None),
+            query(None, Some("/a"), Some("if")),
+            query(None, Some("/a"), Some("1if=urn:x")),
+            query(None, Some("/a"), Some("if=")),
+            query(None, None, Some("if=urn:x")),
+        ] {
+            assert!(matches!(build_filter(&invalid), Err(ApiError::BadRequest(_))), "{:?} was accepted", invalid);
+        }
+    }
+
+    #[test]
+    fn checks_the_xpath_capability() {
+        let filter = ("<filter type=\"xpath\" select=\"/a\"/>".to_string(), true);
+        assert!(is_unsupported(build_get(&hello(&[]), Some(&filter), None)));
+        assert_eq!(
+            build_get(&hello(&[XPATH]), Some(&filter), None).unwrap(),
+            "<get><filter type=\"xpath\" select=\"/a\"/></get>"
+        );
+        let subtree = ("<filter type=\"subtree\"><a/></filter>".to_string(), false);
+        assert!(build_get(&hello(&[]), Some(&subtree), None).is_ok());
+        assert_eq!(build_get(&hello(&[]), None, None).unwrap(), "<get></get>");
+    }
+
+    #[test]
+    fn checks_the_with_defaults_modes() {
+        let capability = format!("{}?basic-mode=explicit&also-supported=report-all,trim", WITH_DEFAULTS);
+        let device = hello(&[capability.as_str()]);
+        for mode in ["explicit", "report-all", "trim"] {
+            assert_eq!(
+                build_get(&device, None, Some(mode)).unwrap(),
+                format!("<get><with-defaults xmlns=\"{}\">{}</with-defaults></get>", WITH_DEFAULTS_NAMESPACE, mode)
+            );
+        }
+        assert!(is_unsupported(build_get(&device, None, Some("report-all-tagged"))));
+        assert!(is_unsupported(build_get(&hello(&[]), None, Some("explicit"))));
+    }
+}
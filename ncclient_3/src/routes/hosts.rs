@@ -0,0 +1,601 @@
+use actix_web::{delete, get, patch, post, put, web, HttpResponse};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::collector::{forget_host, HostStatus};
+use crate::credentials::CredentialProfiles;
+use crate::errors::ApiError;
+use crate::host_store::HostStore;
+use crate::netconf::Timeouts;
+use crate::session::SessionPool;
+use crate::tags::{self, Selector};
+use crate::{AppState, AuthMethod, HostParameters, PrivateKey};
+
+/// Connection settings of a host, as given to `POST /hosts`, `PUT /hosts/{host}` and `/add_host`.
+///
+/// At least one authentication method or a credential profile must be given. Methods are
+/// tried in the order private key, ssh-agent, password.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HostSettings {
+    /// The NETCONF port, usually `830`.
+    port: u16,
+    /// The username, which may be omitted if the credential profile sets it.
+    #[serde(default)]
+    user: String,
+    /// Password of the user, tried after the private key and the ssh-agent.
+    password: Option<String>,
+    /// Inline PEM encoded private key.
+    private_key: Option<String>,
+    /// Path to a PEM encoded private key file.
+    private_key_path: Option<PathBuf>,
+    /// Passphrase of the private key.
+    passphrase: Option<String>,
+    /// Whether to try the identities of the local ssh-agent.
+    #[serde(default)]
+    use_agent: bool,
+    /// Connect, handshake and RPC timeouts in seconds, overriding the global defaults.
+    #[serde(default)]
+    timeouts: Timeouts,
+    /// Seconds between background collections, overriding the global default. `0` disables polling.
+    poll_interval: Option<u64>,
+    /// The credential profile replacing the user and authentication methods of the host.
+    credential_profile: Option<String>,
+    /// Metadata of the host, such as `site`, `role` or `vendor`.
+    #[serde(default)]
+    tags: BTreeMap<String, String>,
+    /// The groups of the host.
+    #[serde(default)]
+    groups: BTreeSet<String>,
+}
+
+impl HostSettings {
+    /// Validates the settings and converts them to host parameters.
+    ///
+    /// The credentials are checked by `store_host`, against the credential profiles.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The hostname or IP address of the device, which must resolve.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the parameters, or an `ApiError` if the settings are invalid.
+    pub async fn into_parameters(self, host: &str) -> Result<HostParameters, ApiError> {
+        let credentials = Credentials {
+            private_key: private_key(self.private_key, self.private_key_path)?,
+            passphrase: self.passphrase,
+            use_agent: self.use_agent,
+            password: self.password,
+        };
+        let parameters = HostParameters {
+            port: self.port,
+            user: self.user,
+            auth_methods: credentials.into_methods(),
+            timeouts: self.timeouts,
+            poll_interval: self.poll_interval,
+            credential_profile: self.credential_profile,
+            tags: self.tags,
+            groups: self.groups,
+        };
+        tags::validate(&parameters.tags, &parameters.groups).map_err(ApiError::BadRequest)?;
+        validate_address(host, self.port).await?;
+        Ok(parameters)
+    }
+}
+
+/// Struct representing the request body for creating a host.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateHostRequest {
+    /// The hostname or IP address of the device.
+    pub host: String,
+    /// The connection settings of the host.
+    #[serde(flatten)]
+    pub settings: HostSettings,
+}
+
+/// Struct representing the request body for updating some settings of a host.
+///
+/// Absent fields are left unchanged. `null` removes a password, private key, passphrase,
+/// polling interval or credential profile. Tags and groups are replaced as a whole.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct HostPatch {
+    /// The new port number.
+    port: Option<u16>,
+    /// The new username.
+    user: Option<String>,
+    /// The new password, or `null` to remove it.
+    #[serde(default, deserialize_with = "present")]
+    password: Option<Option<String>>,
+    /// The new inline private key in PEM format, or `null` to remove it.
+    #[serde(default, deserialize_with = "present")]
+    private_key: Option<Option<String>>,
+    /// The new path of the private key file, or `null` to remove it.
+    #[serde(default, deserialize_with = "present")]
+    private_key_path: Option<Option<PathBuf>>,
+    /// The new passphrase of the private key, or `null` to remove it.
+    #[serde(default, deserialize_with = "present")]
+    passphrase: Option<Option<String>>,
+    /// Whether to try the identities of the local ssh-agent.
+    use_agent: Option<bool>,
+    /// The new timeouts, replacing all of them.
+    timeouts: Option<Timeouts>,
+    /// The new polling interval in seconds, or `null` to use the global default.
+    #[serde(default, deserialize_with = "present")]
+    poll_interval: Option<Option<u64>>,
+    /// The new credential profile, or `null` to use the credentials of the host.
+    #[serde(default, deserialize_with = "present")]
+    credential_profile: Option<Option<String>>,
+    /// The new tags, replacing all of them.
+    tags: Option<BTreeMap<String, String>>,
+    /// The new groups, replacing all of them.
+    groups: Option<BTreeSet<String>>,
+}
+
+/// Deserializes a field that is present, possibly `null`, so it can be told apart from an absent one.
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Credentials of a host or profile, from which the ordered authentication methods are built.
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub private_key: Option<PrivateKey>,
+    pub passphrase: Option<String>,
+    pub use_agent: bool,
+    pub password: Option<String>,
+}
+
+impl Credentials {
+    /// Extracts the credentials from configured authentication methods.
+    pub fn from_methods(methods: &[AuthMethod]) -> Self {
+        let mut credentials = Credentials::default();
+        for method in methods {
+            match method {
+                AuthMethod::PrivateKey { key, passphrase } => {
+                    credentials.private_key = Some(key.clone());
+                    credentials.passphrase = passphrase.clone();
+                }
+                AuthMethod::Agent => credentials.use_agent = true,
+                AuthMethod::Password(password) => credentials.password = Some(password.clone()),
+            }
+        }
+        credentials
+    }
+
+    /// Builds the authentication methods in the order private key, ssh-agent, password.
+    pub fn into_methods(self) -> Vec<AuthMethod> {
+        let mut methods = vec![];
+        if let Some(key) = self.private_key {
+            methods.push(AuthMethod::PrivateKey { key, passphrase: self.passphrase });
+        }
+        if self.use_agent {
+            methods.push(AuthMethod::Agent);
+        }
+        if let Some(password) = self.password {
+            methods.push(AuthMethod::Password(password));
+        }
+        methods
+    }
+}
+
+/// Returns the private key given inline or as a path, which are mutually exclusive.
+pub fn private_key(pem: Option<String>, path: Option<PathBuf>) -> Result<Option<PrivateKey>, ApiError> {
+    match (pem, path) {
+        (Some(_), Some(_)) => Err(ApiError::BadRequest(
+            "private_key and private_key_path are mutually exclusive".to_string(),
+        )),
+        (Some(pem), None) => Ok(Some(PrivateKey::Inline(pem))),
+        (None, Some(path)) => Ok(Some(PrivateKey::File(path))),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Checks that a host references an existing credential profile, or has its own
+/// authentication methods and user.
+///
+/// The caller holds the host dictionary, which `delete_profile` holds while checking
+/// that no host references the profile.
+pub fn validate_credentials(parameters: &HostParameters, profiles: &CredentialProfiles) -> Result<(), ApiError> {
+    let profile = match &parameters.credential_profile {
+        Some(name) => Some(
+            profiles
+                .get(name)
+                .ok_or_else(|| ApiError::BadRequest(format!("unknown credential profile {}", name)))?,
+        ),
+        None => None,
+    };
+    if profile.is_none() && parameters.auth_methods.is_empty() {
+        return Err(ApiError::BadRequest(
+            "at least one of password, private_key, private_key_path, use_agent or credential_profile is required"
+                .to_string(),
+        ));
+    }
+    if parameters.user.is_empty() && profile.and_then(|p| p.user).is_none() {
+        return Err(ApiError::BadRequest("user is required unless the credential profile sets it".to_string()));
+    }
+    Ok(())
+}
+
+/// Checks that the port is not `0` and that the host is an IP address or a name that resolves.
+async fn validate_address(host: &str, port: u16) -> Result<(), ApiError> {
+    if port == 0 {
+        return Err(ApiError::BadRequest("port must be between 1 and 65535".to_string()));
+    }
+    if host.parse::<IpAddr>().is_ok() {
+        return Ok(());
+    }
+    // Name resolution is blocking, so it runs on the blocking thread pool.
+    let name = host.to_string();
+    let resolved = web::block(move || (name.as_str(), port).to_socket_addrs().map(|mut a| a.next().is_some())).await?;
+    match resolved {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ApiError::BadRequest(format!("host {} did not resolve to any address", host))),
+        Err(e) => Err(ApiError::BadRequest(format!("host {} does not resolve: {}", host, e))),
+    }
+}
+
+/// Description of a registered host, without its secrets.
+#[derive(Debug, Clone, Serialize)]
+struct HostSummary {
+    /// The hostname or IP address of the device.
+    host: String,
+    /// The port number for the host.
+    port: u16,
+    /// The username for the host, empty if the credential profile sets it.
+    user: String,
+    /// The authentication methods tried, in order: `private_key`, `agent` or `password`.
+    auth_methods: Vec<&'static str>,
+    /// The timeouts of the host, unset ones using the global defaults.
+    timeouts: Timeouts,
+    /// Seconds between background collections, if the host overrides the global default.
+    poll_interval: Option<u64>,
+    /// The credential profile replacing the user and authentication methods of the host.
+    credential_profile: Option<String>,
+    /// Metadata of the host, such as `site`, `role` or `vendor`.
+    tags: BTreeMap<String, String>,
+    /// The groups of the host.
+    groups: BTreeSet<String>,
+    /// The outcome of the last collections, if the host was collected.
+    status: Option<HostStatus>,
+}
+
+impl HostSummary {
+    /// Describes a host from its parameters and the application state.
+    fn new(host: &str, parameters: &HostParameters, state: &AppState) -> Self {
+        HostSummary {
+            host: host.to_string(),
+            port: parameters.port,
+            user: parameters.user.clone(),
+            auth_methods: parameters
+                .auth_methods
+                .iter()
+                .map(|method| match method {
+                    AuthMethod::PrivateKey { .. } => "private_key",
+                    AuthMethod::Agent => "agent",
+                    AuthMethod::Password(_) => "password",
+                })
+                .collect(),
+            timeouts: parameters.timeouts,
+            poll_interval: parameters.poll_interval,
+            credential_profile: parameters.credential_profile.clone(),
+            tags: parameters.tags.clone(),
+            groups: parameters.groups.clone(),
+            status: state.poll_status.lock().unwrap().get(host).cloned(),
+        }
+    }
+}
+
+/// Checks the credentials of a host, writes it through to the store, then inserts it into
+/// the dictionary.
+///
+/// Checking under the lock of the dictionary keeps `delete_profile` from removing the
+/// profile of the host in between. The write is blocking, so it must run on the blocking
+/// thread pool with `web::block`.
+///
+/// # Returns
+///
+/// A `Result` which is an `ApiError` if the credentials are invalid, or if the host cannot
+/// be stored.
+pub fn store_host(
+    host_dictionary: &mut HashMap<String, HostParameters>,
+    host_store: &HostStore,
+    profiles: &CredentialProfiles,
+    host: &str,
+    parameters: HostParameters,
+) -> Result<(), ApiError> {
+    validate_credentials(&parameters, profiles)?;
+    host_store.put(host, &parameters).map_err(|e| match e.kind() {
+        // Secrets without a master key, which the client may replace.
+        io::ErrorKind::PermissionDenied => ApiError::BadRequest(e.to_string()),
+        _ => ApiError::Internal(format!("failed to store host {}: {}", host, e)),
+    })?;
+    host_dictionary.insert(host.to_string(), parameters);
+    Ok(())
+}
+
+/// Struct representing the query parameters selecting hosts by tags and group.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SelectorQuery {
+    /// Comma separated `key:value` or `key` terms the hosts must all match.
+    pub tag: Option<String>,
+    /// The group the hosts must be in.
+    pub group: Option<String>,
+}
+
+impl SelectorQuery {
+    /// Parses the selector.
+    pub fn selector(&self) -> Result<Selector, ApiError> {
+        Selector::parse(self.tag.as_deref(), self.group.as_deref()).map_err(ApiError::BadRequest)
+    }
+}
+
+/// HTTP GET endpoint to list the registered hosts with their settings and last poll status.
+///
+/// # Arguments
+///
+/// * `query` - A `web::Query<SelectorQuery>` optionally selecting the hosts by tags and group.
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+/// * `state` - A `web::Data<AppState>` representing the application state.
+///
+/// # Returns
+///
+/// A `Result` containing the hosts sorted by name, without passwords, keys or passphrases,
+/// or an `ApiError` if the selector is invalid.
+#[get("/hosts")]
+pub async fn list_hosts(
+    query: web::Query<SelectorQuery>,
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let selector = query.selector()?;
+    let mut hosts: Vec<HostSummary> = host_dictionary
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, parameters)| selector.matches(parameters))
+        .map(|(host, parameters)| HostSummary::new(host, parameters, &state))
+        .collect();
+    hosts.sort_by(|a, b| a.host.cmp(&b.host));
+    Ok(HttpResponse::Ok().json(hosts))
+}
+
+/// HTTP POST endpoint to register a new host.
+///
+/// # Arguments
+///
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+/// * `host_store` - A `web::Data<HostStore>` persisting the host dictionary.
+/// * `profiles` - A `web::Data<CredentialProfiles>` holding the credential profiles.
+/// * `state` - A `web::Data<AppState>` representing the application state.
+/// * `request` - A `web::Json<CreateHostRequest>` containing the host and its settings.
+///
+/// # Returns
+///
+/// A `Result` containing the created host with status `201`, or an `ApiError` if the request
+/// is invalid, the host already exists or cannot be stored.
+#[post("/hosts")]
+pub async fn create_host(
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+    host_store: web::Data<HostStore>,
+    profiles: web::Data<CredentialProfiles>,
+    state: web::Data<AppState>,
+    request: web::Json<CreateHostRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let CreateHostRequest { host, settings } = request.into_inner();
+    let parameters = settings.into_parameters(&host).await?;
+    let summary = HostSummary::new(&host, &parameters, &state);
+    // The store is written on the blocking thread pool, holding the dictionary.
+    web::block(move || {
+        let mut host_dictionary = host_dictionary.lock().unwrap();
+        if host_dictionary.contains_key(&host) {
+            return Err(ApiError::Conflict(format!("host {} already exists", host)));
+        }
+        store_host(&mut host_dictionary, host_store.get_ref(), &profiles, &host, parameters)
+    })
+    .await??;
+    Ok(HttpResponse::Created().json(summary))
+}
+
+/// HTTP GET endpoint to retrieve a registered host.
+///
+/// # Arguments
+///
+/// * `host` - A `web::Path<String>` representing the host.
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+/// * `state` - A `web::Data<AppState>` representing the application state.
+///
+/// # Returns
+///
+/// A `Result` containing the host without its secrets, or an `ApiError` if the host is unknown.
+#[get("/hosts/{host}")]
+pub async fn get_host(
+    host: web::Path<String>,
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let host_dictionary = host_dictionary.lock().unwrap();
+    let parameters = host_dictionary
+        .get(host.as_str())
+        .ok_or_else(|| ApiError::UnknownHost(host.to_string()))?;
+    Ok(HttpResponse::Ok().json(HostSummary::new(&host, parameters, &state)))
+}
+
+/// HTTP PUT endpoint to replace every setting of a registered host.
+///
+/// # Arguments
+///
+/// * `host` - A `web::Path<String>` representing the host.
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+/// * `host_store` - A `web::Data<HostStore>` persisting the host dictionary.
+/// * `profiles` - A `web::Data<CredentialProfiles>` holding the credential profiles.
+/// * `state` - A `web::Data<AppState>` representing the application state.
+/// * `request` - A `web::Json<HostSettings>` containing the new settings.
+///
+/// # Returns
+///
+/// A `Result` containing the updated host, or an `ApiError` if the host is unknown, the
+/// request is invalid or the host cannot be stored.
+#[put("/hosts/{host}")]
+pub async fn replace_host(
+    host: web::Path<String>,
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+    host_store: web::Data<HostStore>,
+    profiles: web::Data<CredentialProfiles>,
+    state: web::Data<AppState>,
+    request: web::Json<HostSettings>,
+) -> Result<HttpResponse, ApiError> {
+    let host = host.into_inner();
+    let parameters = request.into_inner().into_parameters(&host).await?;
+    let summary = HostSummary::new(&host, &parameters, &state);
+    // The store is written on the blocking thread pool, holding the dictionary.
+    web::block(move || {
+        let mut host_dictionary = host_dictionary.lock().unwrap();
+        if !host_dictionary.contains_key(&host) {
+            return Err(ApiError::UnknownHost(host));
+        }
+        store_host(&mut host_dictionary, host_store.get_ref(), &profiles, &host, parameters)
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+/// HTTP PATCH endpoint to change some settings of a registered host.
+///
+/// # Arguments
+///
+/// * `host` - A `web::Path<String>` representing the host.
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+/// * `host_store` - A `web::Data<HostStore>` persisting the host dictionary.
+/// * `profiles` - A `web::Data<CredentialProfiles>` holding the credential profiles.
+/// * `state` - A `web::Data<AppState>` representing the application state.
+/// * `request` - A `web::Json<HostPatch>` containing the settings to change.
+///
+/// # Returns
+///
+/// A `Result` containing the updated host, or an `ApiError` if the host is unknown, the
+/// result is invalid or the host cannot be stored.
+#[patch("/hosts/{host}")]
+pub async fn update_host(
+    host: web::Path<String>,
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+    host_store: web::Data<HostStore>,
+    profiles: web::Data<CredentialProfiles>,
+    state: web::Data<AppState>,
+    request: web::Json<HostPatch>,
+) -> Result<HttpResponse, ApiError> {
+    let host = host.into_inner();
+    let patch = request.into_inner();
+    if patch.port == Some(0) {
+        return Err(ApiError::BadRequest("port must be between 1 and 65535".to_string()));
+    }
+    // The patch is applied and stored on the blocking thread pool, holding the dictionary.
+    let summary = web::block(move || -> Result<HostSummary, ApiError> {
+        let mut host_dictionary = host_dictionary.lock().unwrap();
+        let mut parameters = host_dictionary
+            .get(&host)
+            .cloned()
+            .ok_or_else(|| ApiError::UnknownHost(host.clone()))?;
+
+        // Apply the changed credentials on top of the current ones.
+        let mut credentials = Credentials::from_methods(&parameters.auth_methods);
+        match (patch.private_key, patch.private_key_path) {
+            (Some(Some(pem)), path) => credentials.private_key = private_key(Some(pem), path.flatten())?,
+            (pem, Some(Some(path))) => credentials.private_key = private_key(pem.flatten(), Some(path))?,
+            (Some(None), _) | (_, Some(None)) => credentials.private_key = None,
+            (None, None) => {}
+        }
+        if let Some(passphrase) = patch.passphrase {
+            credentials.passphrase = passphrase;
+        }
+        if let Some(use_agent) = patch.use_agent {
+            credentials.use_agent = use_agent;
+        }
+        if let Some(password) = patch.password {
+            credentials.password = password;
+        }
+        parameters.auth_methods = credentials.into_methods();
+
+        // Apply the other settings.
+        if let Some(port) = patch.port {
+            parameters.port = port;
+        }
+        if let Some(user) = patch.user {
+            parameters.user = user;
+        }
+        if let Some(timeouts) = patch.timeouts {
+            parameters.timeouts = timeouts;
+        }
+        if let Some(poll_interval) = patch.poll_interval {
+            parameters.poll_interval = poll_interval;
+        }
+        if let Some(credential_profile) = patch.credential_profile {
+            parameters.credential_profile = credential_profile;
+        }
+        if let Some(tags) = patch.tags {
+            parameters.tags = tags;
+        }
+        if let Some(groups) = patch.groups {
+            parameters.groups = groups;
+        }
+        tags::validate(&parameters.tags, &parameters.groups).map_err(ApiError::BadRequest)?;
+
+        let summary = HostSummary::new(&host, &parameters, &state);
+        store_host(&mut host_dictionary, host_store.get_ref(), &profiles, &host, parameters)?;
+        Ok(summary)
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+/// HTTP DELETE endpoint to unregister a host.
+///
+/// The host is removed from the store, its sessions are closed, and its status, inventory
+/// and metric series are removed.
+///
+/// # Arguments
+///
+/// * `host` - A `web::Path<String>` representing the host.
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+/// * `host_store` - A `web::Data<HostStore>` persisting the host dictionary.
+/// * `session_pool` - A `web::Data<SessionPool>` holding the persistent NETCONF sessions.
+/// * `state` - A `web::Data<AppState>` representing the application state.
+///
+/// # Returns
+///
+/// A `Result` containing an empty `204` response, or an `ApiError` if the host is unknown
+/// or cannot be removed from the store.
+#[delete("/hosts/{host}")]
+pub async fn delete_host(
+    host: web::Path<String>,
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+    host_store: web::Data<HostStore>,
+    session_pool: web::Data<SessionPool>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let host = host.into_inner();
+    // The store is written on the blocking thread pool, holding the dictionary. Collections
+    // of the host still in flight record nothing once it is removed from the dictionary.
+    let host = web::block(move || {
+        let mut host_dictionary = host_dictionary.lock().unwrap();
+        if !host_dictionary.contains_key(&host) {
+            return Err(ApiError::UnknownHost(host));
+        }
+        host_store
+            .delete(&host)
+            .map_err(|e| ApiError::Internal(format!("failed to remove host {}: {}", host, e)))?;
+        host_dictionary.remove(&host);
+        Ok(host)
+    })
+    .await??;
+    session_pool.forget(&host);
+    forget_host(&state, &host);
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,682 @@
+use actix_web::{get, post, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::ansible;
+use crate::credentials::CredentialProfiles;
+use crate::errors::ApiError;
+use crate::host_store::HostStore;
+use crate::netconf::Timeouts;
+use crate::routes::hosts::{validate_credentials, Credentials, HostSettings};
+use crate::utils::{csv_field, parse_csv};
+use crate::{HostParameters, PrivateKey};
+
+/// Port of the imported hosts that do not set one, the NETCONF over SSH port.
+const DEFAULT_PORT: u16 = 830;
+
+/// Columns of the CSV export, which hold no secret, followed by a `tag.<key>` column per tag.
+const EXPORT_COLUMNS: [&str; 11] = [
+    "host",
+    "port",
+    "user",
+    "private_key_path",
+    "use_agent",
+    "credential_profile",
+    "poll_interval",
+    "connect_timeout",
+    "handshake_timeout",
+    "rpc_timeout",
+    "groups",
+];
+
+/// Columns accepted by the CSV import, the export columns and the secrets, besides the
+/// `tag.<key>` columns.
+const IMPORT_COLUMNS: [&str; 14] = [
+    "host",
+    "port",
+    "user",
+    "private_key_path",
+    "use_agent",
+    "credential_profile",
+    "poll_interval",
+    "connect_timeout",
+    "handshake_timeout",
+    "rpc_timeout",
+    "groups",
+    "password",
+    "private_key",
+    "passphrase",
+];
+
+/// Struct representing the query parameters of the import endpoint.
+#[derive(Debug, Clone, Deserialize)]
+struct ImportQuery {
+    /// `csv`, `yaml` or `ansible`.
+    format: String,
+    /// Whether to only validate the hosts, without registering them.
+    #[serde(default)]
+    dry_run: bool,
+    /// The Ansible group whose hosts are imported, every host if absent.
+    group: Option<String>,
+}
+
+/// Struct representing the query parameters of the export endpoint.
+#[derive(Debug, Clone, Deserialize)]
+struct ExportQuery {
+    /// `csv`, `yaml` or `ansible`.
+    format: String,
+}
+
+/// A host read from an import document, before it is validated.
+struct ImportRow {
+    /// The CSV line, or the position of the host in a YAML document or Ansible inventory.
+    row: usize,
+    /// The hostname or IP address of the device, if found.
+    host: Option<String>,
+    /// The settings of the host, or the reason they cannot be read.
+    settings: Result<HostSettings, String>,
+}
+
+/// Outcome of the import of a host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    /// The host was not registered.
+    Create,
+    /// The host was registered with other settings.
+    Update,
+    /// The host was registered with the same settings.
+    Unchanged,
+    /// The host is invalid and was not imported.
+    Error,
+}
+
+/// Result of the import of a host.
+#[derive(Debug, Clone, Serialize)]
+struct RowResult {
+    /// The CSV line, or the position of the host in a YAML document or Ansible inventory.
+    row: usize,
+    /// The hostname or IP address of the device, if found.
+    host: Option<String>,
+    /// What the import did, or would do in a dry run, with the host.
+    action: Action,
+    /// The reason the host is invalid.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Results of an import.
+#[derive(Debug, Clone, Serialize)]
+struct ImportReport {
+    /// Whether the hosts were only validated.
+    dry_run: bool,
+    /// The number of hosts registered.
+    created: usize,
+    /// The number of registered hosts whose settings were replaced.
+    updated: usize,
+    /// The number of registered hosts whose settings were the same.
+    unchanged: usize,
+    /// The number of invalid hosts, or hosts that could not be stored.
+    failed: usize,
+    /// The result of every host, in the order of the document.
+    rows: Vec<RowResult>,
+}
+
+/// The settings of a host written by the export, without passwords, inline keys or passphrases.
+#[derive(Debug, Clone, Serialize)]
+struct ExportedHost {
+    /// The port number for the host.
+    port: u16,
+    /// The username for the host.
+    user: String,
+    /// The path of the private key file, if the key is not inline.
+    private_key_path: Option<PathBuf>,
+    /// Whether the identities of the local ssh-agent are tried.
+    use_agent: bool,
+    /// The timeouts of the host.
+    timeouts: Timeouts,
+    /// Seconds between background collections, if the host overrides the global default.
+    poll_interval: Option<u64>,
+    /// The credential profile replacing the user and authentication methods of the host.
+    credential_profile: Option<String>,
+    /// Metadata of the host, such as `site`, `role` or `vendor`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    tags: BTreeMap<String, String>,
+    /// The groups of the host.
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    groups: BTreeSet<String>,
+}
+
+impl ExportedHost {
+    /// Removes the secrets from the parameters of a host.
+    fn new(parameters: &HostParameters) -> Self {
+        let credentials = Credentials::from_methods(&parameters.auth_methods);
+        ExportedHost {
+            port: parameters.port,
+            user: parameters.user.clone(),
+            private_key_path: match credentials.private_key {
+                Some(PrivateKey::File(path)) => Some(path),
+                _ => None,
+            },
+            use_agent: credentials.use_agent,
+            timeouts: parameters.timeouts,
+            poll_interval: parameters.poll_interval,
+            credential_profile: parameters.credential_profile.clone(),
+            tags: parameters.tags.clone(),
+            groups: parameters.groups.clone(),
+        }
+    }
+
+    /// Returns the settings keyed by the export columns following `host`, with `null` for
+    /// unset settings, then the tags keyed by `tag.<key>`.
+    fn fields(&self) -> Vec<(String, Value)> {
+        let mut fields = vec![
+            ("port", json!(self.port)),
+            ("user", json!(Some(&self.user).filter(|user| !user.is_empty()))),
+            ("private_key_path", json!(self.private_key_path)),
+            ("use_agent", json!(self.use_agent)),
+            ("credential_profile", json!(self.credential_profile)),
+            ("poll_interval", json!(self.poll_interval)),
+            ("connect_timeout", json!(self.timeouts.connect)),
+            ("handshake_timeout", json!(self.timeouts.handshake)),
+            ("rpc_timeout", json!(self.timeouts.rpc)),
+            ("groups", json!(self.groups)),
+        ]
+        .into_iter()
+        .map(|(field, value)| (field.to_string(), value))
+        .collect::<Vec<_>>();
+        fields.extend(self.tags.iter().map(|(key, value)| (format!("tag.{}", key), json!(value))));
+        fields
+    }
+}
+
+/// Builds the settings of a host from flat fields, as found in CSV files and Ansible
+/// inventories. Empty fields are ignored, and the port defaults to `DEFAULT_PORT`. The
+/// `groups` field separates the groups with `;`, and the `tag.<key>` fields set the tags.
+fn settings_from_fields(fields: &BTreeMap<String, String>) -> Result<HostSettings, String> {
+    let mut settings = Map::new();
+    let mut timeouts = Map::new();
+    let mut tags = Map::new();
+    settings.insert("port".to_string(), json!(DEFAULT_PORT));
+    for (field, value) in fields {
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        let number = || {
+            value
+                .parse::<u64>()
+                .map(Value::from)
+                .map_err(|_| format!("{} must be a non-negative integer, got {:?}", field, value))
+        };
+        if let Some(key) = field.strip_prefix("tag.") {
+            tags.insert(key.to_string(), Value::String(value.to_string()));
+            continue;
+        }
+        match field.as_str() {
+            "host" => {}
+            "groups" => {
+                let groups: Vec<&str> = value
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|group| !group.is_empty())
+                    .collect();
+                settings.insert(field.clone(), json!(groups));
+            }
+            "port" | "poll_interval" => {
+                settings.insert(field.clone(), number()?);
+            }
+            "connect_timeout" | "handshake_timeout" | "rpc_timeout" => {
+                timeouts.insert(field.trim_end_matches("_timeout").to_string(), number()?);
+            }
+            "use_agent" => {
+                let use_agent = match value.to_lowercase().as_str() {
+                    "true" | "yes" | "1" => true,
+                    "false" | "no" | "0" => false,
+                    _ => return Err(format!("use_agent must be true or false, got {:?}", value)),
+                };
+                settings.insert(field.clone(), Value::Bool(use_agent));
+            }
+            _ => {
+                settings.insert(field.clone(), Value::String(value.to_string()));
+            }
+        }
+    }
+    settings.insert("timeouts".to_string(), Value::Object(timeouts));
+    settings.insert("tags".to_string(), Value::Object(tags));
+    serde_json::from_value(Value::Object(settings)).map_err(|e| e.to_string())
+}
+
+/// Builds an import row from flat fields, which must hold a `host`.
+fn row_from_fields(row: usize, fields: BTreeMap<String, String>) -> ImportRow {
+    let host = fields
+        .get("host")
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty());
+    let settings = match host {
+        Some(_) => settings_from_fields(&fields),
+        None => Err("missing host".to_string()),
+    };
+    ImportRow { row, host, settings }
+}
+
+/// Reads a CSV document whose header names some of the `IMPORT_COLUMNS`, including `host`,
+/// and `tag.<key>` columns.
+fn read_csv(csv: &str) -> Result<Vec<ImportRow>, ApiError> {
+    let mut records = parse_csv(csv).map_err(ApiError::BadRequest)?.into_iter();
+    let header: Vec<String> = match records.next() {
+        Some((_, header)) => header.iter().map(|column| column.trim().to_string()).collect(),
+        None => return Ok(vec![]),
+    };
+    if let Some(column) = header
+        .iter()
+        .find(|column| !IMPORT_COLUMNS.contains(&column.as_str()) && !column.starts_with("tag."))
+    {
+        return Err(ApiError::BadRequest(format!(
+            "unknown column {:?}, expected tag.<key> columns and some of {}",
+            column,
+            IMPORT_COLUMNS.join(", ")
+        )));
+    }
+    if !header.iter().any(|column| column == "host") {
+        return Err(ApiError::BadRequest("missing host column".to_string()));
+    }
+
+    Ok(records
+        .map(|(line, record)| {
+            if record.len() != header.len() {
+                return ImportRow {
+                    row: line,
+                    host: header
+                        .iter()
+                        .position(|column| column == "host")
+                        .and_then(|position| record.get(position))
+                        .map(|host| host.trim().to_string())
+                        .filter(|host| !host.is_empty()),
+                    settings: Err(format!("expected {} fields, got {}", header.len(), record.len())),
+                };
+            }
+            row_from_fields(line, header.iter().cloned().zip(record).collect())
+        })
+        .collect())
+}
+
+/// Reads a YAML document, either a mapping of the hosts to their settings, or a list of
+/// settings with a `host` field. The settings are those of `POST /hosts`.
+fn read_yaml(yaml: &str) -> Result<Vec<ImportRow>, ApiError> {
+    let document: serde_yaml::Value =
+        serde_yaml::from_str(yaml).map_err(|e| ApiError::BadRequest(format!("invalid YAML: {}", e)))?;
+    let entries: Vec<(Option<String>, serde_yaml::Value)> = match document {
+        serde_yaml::Value::Null => vec![],
+        serde_yaml::Value::Mapping(hosts) => hosts
+            .into_iter()
+            .map(|(host, settings)| (host.as_str().map(str::to_string), settings))
+            .collect(),
+        serde_yaml::Value::Sequence(hosts) => hosts
+            .into_iter()
+            .map(|mut settings| {
+                let host = settings
+                    .as_mapping_mut()
+                    .and_then(|settings| settings.remove("host"))
+                    .and_then(|host| host.as_str().map(str::to_string));
+                (host, settings)
+            })
+            .collect(),
+        _ => return Err(ApiError::BadRequest("expected a mapping or a list of hosts".to_string())),
+    };
+
+    Ok(entries
+        .into_iter()
+        .enumerate()
+        .map(|(position, (host, settings))| {
+            let settings = match host {
+                Some(_) => settings_from_yaml(settings),
+                None => Err("missing host".to_string()),
+            };
+            ImportRow { row: position + 1, host, settings }
+        })
+        .collect())
+}
+
+/// Deserializes the settings of a host from YAML, with the port defaulting to `DEFAULT_PORT`.
+fn settings_from_yaml(settings: serde_yaml::Value) -> Result<HostSettings, String> {
+    let mut settings = match settings {
+        serde_yaml::Value::Null => serde_yaml::Mapping::new(),
+        serde_yaml::Value::Mapping(settings) => settings,
+        _ => return Err("expected a mapping of settings".to_string()),
+    };
+    if !settings.contains_key("port") {
+        settings.insert("port".into(), DEFAULT_PORT.into());
+    }
+    serde_yaml::from_value(serde_yaml::Value::Mapping(settings)).map_err(|e| e.to_string())
+}
+
+/// Reads an Ansible inventory, mapping the variables of the hosts with `ansible::VARIABLES`.
+fn read_ansible(inventory: &str, group: Option<&str>) -> Result<Vec<ImportRow>, ApiError> {
+    let hosts = ansible::parse(inventory, group).map_err(ApiError::BadRequest)?;
+    Ok(hosts
+        .iter()
+        .enumerate()
+        .map(|(position, host)| row_from_fields(position + 1, host.fields()))
+        .collect())
+}
+
+/// HTTP POST endpoint to register many hosts from a CSV file, a YAML file or an Ansible inventory.
+///
+/// Every host is validated like in `POST /hosts`, and the valid hosts are registered or
+/// replace the existing ones, even if other hosts are invalid. Secrets omitted for an
+/// existing host, as in an export, are kept from it.
+///
+/// # Arguments
+///
+/// * `query` - A `web::Query<ImportQuery>` selecting the format, the dry run and the Ansible group.
+/// * `body` - A `String` containing the document to import.
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+/// * `host_store` - A `web::Data<HostStore>` persisting the host dictionary.
+/// * `profiles` - A `web::Data<CredentialProfiles>` holding the credential profiles.
+///
+/// # Returns
+///
+/// A `Result` containing the outcome of every host, or an `ApiError` if the format is
+/// unknown or the document cannot be read.
+#[post("/hosts/import")]
+pub async fn import_hosts(
+    query: web::Query<ImportQuery>,
+    body: String,
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+    host_store: web::Data<HostStore>,
+    profiles: web::Data<CredentialProfiles>,
+) -> Result<HttpResponse, ApiError> {
+    if query.group.is_some() && query.format != "ansible" {
+        return Err(ApiError::BadRequest("group is only supported by the ansible format".to_string()));
+    }
+    let rows = match query.format.as_str() {
+        "csv" => read_csv(&body)?,
+        "yaml" => read_yaml(&body)?,
+        "ansible" => read_ansible(&body, query.group.as_deref())?,
+        other => {
+            return Err(ApiError::BadRequest(format!(
+                "unknown format {:?}, expected csv, yaml or ansible",
+                other
+            )))
+        }
+    };
+
+    // Validate every host before touching the dictionary.
+    let mut seen = HashSet::new();
+    let mut validated = vec![];
+    for row in rows {
+        let parameters = match (&row.host, row.settings) {
+            (_, Err(e)) => Err(e),
+            (Some(host), Ok(_)) if !seen.insert(host.clone()) => Err(format!("duplicate host {}", host)),
+            (Some(host), Ok(settings)) => settings.into_parameters(host).await.map_err(|e| e.to_string()),
+            (None, Ok(_)) => Err("missing host".to_string()),
+        };
+        validated.push((row.row, row.host, parameters));
+    }
+
+    // Complete, check and register the valid hosts on the blocking thread pool, holding
+    // the dictionary. The changed hosts are stored in a single write, unless this is a dry run.
+    let dry_run = query.dry_run;
+    let rows = web::block(move || {
+        let mut host_dictionary = host_dictionary.lock().unwrap();
+        let mut rows = vec![];
+        let mut changes = vec![];
+        for (row, host, parameters) in validated {
+            let outcome = parameters.and_then(|mut parameters| {
+                let host = host.as_deref().unwrap_or_default();
+                let current = host_dictionary.get(host);
+                if let Some(current) = current {
+                    merge_secrets(&mut parameters, current);
+                }
+                validate_credentials(&parameters, &profiles).map_err(|e| e.to_string())?;
+                let action = match current {
+                    None => Action::Create,
+                    Some(current) if *current == parameters => Action::Unchanged,
+                    Some(_) => Action::Update,
+                };
+                if action != Action::Unchanged {
+                    changes.push((host.to_string(), parameters));
+                }
+                Ok(action)
+            });
+            rows.push(match outcome {
+                Ok(action) => RowResult { row, host, action, error: None },
+                Err(e) => RowResult { row, host, action: Action::Error, error: Some(e) },
+            });
+        }
+        if dry_run || changes.is_empty() {
+            return rows;
+        }
+        match host_store.put_all(&changes) {
+            Ok(()) => host_dictionary.extend(changes),
+            // None of the changed hosts was stored.
+            Err(e) => {
+                for row in rows.iter_mut().filter(|row| matches!(row.action, Action::Create | Action::Update)) {
+                    row.action = Action::Error;
+                    row.error = Some(format!("failed to store the hosts: {}", e));
+                }
+            }
+        }
+        rows
+    })
+    .await?;
+
+    let mut report = ImportReport { dry_run, created: 0, updated: 0, unchanged: 0, failed: 0, rows: vec![] };
+    for row in rows {
+        match row.action {
+            Action::Create => report.created += 1,
+            Action::Update => report.updated += 1,
+            Action::Unchanged => report.unchanged += 1,
+            Action::Error => report.failed += 1,
+        }
+        report.rows.push(row);
+    }
+    Ok(HttpResponse::Ok().json(report))
+}
+
+/// Completes the credentials of an imported host with the secrets of the registered host,
+/// which exports omit.
+///
+/// The password, and the inline private key with its passphrase, are kept unless the
+/// imported host sets its own. The passphrase is also kept if the private key is the same.
+fn merge_secrets(parameters: &mut HostParameters, current: &HostParameters) {
+    let current = Credentials::from_methods(&current.auth_methods);
+    let mut credentials = Credentials::from_methods(&parameters.auth_methods);
+    if credentials.password.is_none() {
+        credentials.password = current.password;
+    }
+    match (&credentials.private_key, current.private_key) {
+        (None, Some(key @ PrivateKey::Inline(_))) => {
+            credentials.private_key = Some(key);
+            credentials.passphrase = current.passphrase;
+        }
+        (Some(key), Some(current_key)) if *key == current_key && credentials.passphrase.is_none() => {
+            credentials.passphrase = current.passphrase;
+        }
+        _ => {}
+    }
+    parameters.auth_methods = credentials.into_methods();
+}
+
+/// HTTP GET endpoint to export the registered hosts as CSV, YAML or an Ansible YAML inventory.
+///
+/// # Arguments
+///
+/// * `query` - A `web::Query<ExportQuery>` selecting the format.
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+///
+/// # Returns
+///
+/// A `Result` containing the hosts sorted by name, without passwords, inline keys or
+/// passphrases, or an `ApiError` if the format is unknown.
+#[get("/hosts/export")]
+pub async fn export_hosts(
+    query: web::Query<ExportQuery>,
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+) -> Result<HttpResponse, ApiError> {
+    let hosts: BTreeMap<String, ExportedHost> = host_dictionary
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(host, parameters)| (host.clone(), ExportedHost::new(parameters)))
+        .collect();
+
+    let (content_type, file_name, body) = match query.format.as_str() {
+        "csv" => {
+            // One column per tag key found on any host.
+            let tag_columns: BTreeSet<String> = hosts
+                .values()
+                .flat_map(|exported| exported.tags.keys().map(|key| format!("tag.{}", key)))
+                .collect();
+            let mut columns: Vec<String> = EXPORT_COLUMNS.iter().map(|column| column.to_string()).collect();
+            columns.extend(tag_columns);
+            let mut csv = format!("{}\n", columns.iter().map(|column| csv_field(column)).collect::<Vec<_>>().join(","));
+            for (host, exported) in &hosts {
+                let mut values: HashMap<String, Value> = exported.fields().into_iter().collect();
+                let mut fields = vec![csv_field(host)];
+                for column in &columns[1..] {
+                    fields.push(csv_field(&match values.remove(column).unwrap_or(Value::Null) {
+                        Value::Null => String::new(),
+                        Value::String(value) => value,
+                        Value::Array(items) => items
+                            .iter()
+                            .filter_map(|item| item.as_str())
+                            .collect::<Vec<_>>()
+                            .join(";"),
+                        value => value.to_string(),
+                    }));
+                }
+                csv.push_str(&format!("{}\n", fields.join(",")));
+            }
+            ("text/csv; charset=utf-8", "hosts.csv", csv)
+        }
+        "yaml" => (
+            "application/yaml",
+            "hosts.yaml",
+            serde_yaml::to_string(&hosts).map_err(|e| ApiError::Internal(e.to_string()))?,
+        ),
+        "ansible" => {
+            let hosts: Vec<(String, Vec<(String, Value)>)> = hosts
+                .iter()
+                .map(|(host, exported)| (host.clone(), exported.fields()))
+                .collect();
+            (
+                "application/yaml",
+                "inventory.yaml",
+                ansible::to_yaml(&hosts).map_err(|e| ApiError::Internal(e.to_string()))?,
+            )
+        }
+        other => {
+            return Err(ApiError::BadRequest(format!(
+                "unknown format {:?}, expected csv, yaml or ansible",
+                other
+            )))
+        }
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", file_name)))
+        .body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host_store::{FileFormat, FileStore};
+    use crate::AuthMethod;
+    use actix_web::test::{call_and_read_body, call_and_read_body_json, init_service, TestRequest};
+    use actix_web::App;
+    use std::env;
+
+    fn parameters(auth_methods: Vec<AuthMethod>) -> HostParameters {
+        HostParameters {
+            port: 830,
+            user: "admin".to_string(),
+            auth_methods,
+            timeouts: Timeouts::default(),
+            poll_interval: None,
+            credential_profile: None,
+            tags: BTreeMap::new(),
+            groups: BTreeSet::new(),
+        }
+    }
+
+    fn inline_key(passphrase: Option<&str>) -> AuthMethod {
+        AuthMethod::PrivateKey {
+            key: PrivateKey::Inline("PEM".to_string()),
+            passphrase: passphrase.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn keeps_omitted_secrets() {
+        let current = parameters(vec![inline_key(Some("phrase")), AuthMethod::Password("secret".to_string())]);
+        let mut exported = parameters(vec![]);
+        merge_secrets(&mut exported, &current);
+        assert_eq!(exported, current);
+
+        let mut agent = parameters(vec![AuthMethod::Agent]);
+        merge_secrets(&mut agent, &parameters(vec![AuthMethod::Password("secret".to_string())]));
+        assert_eq!(agent.auth_methods, [AuthMethod::Agent, AuthMethod::Password("secret".to_string())]);
+    }
+
+    #[test]
+    fn keeps_given_secrets() {
+        let current = parameters(vec![inline_key(Some("phrase")), AuthMethod::Password("secret".to_string())]);
+        let file = AuthMethod::PrivateKey { key: PrivateKey::File(PathBuf::from("id_rsa")), passphrase: None };
+        let mut imported = parameters(vec![file.clone(), AuthMethod::Password("other".to_string())]);
+        merge_secrets(&mut imported, &current);
+        assert_eq!(imported.auth_methods, [file, AuthMethod::Password("other".to_string())]);
+
+        // The passphrase belongs to the key.
+        let mut same_key = parameters(vec![inline_key(None)]);
+        merge_secrets(&mut same_key, &current);
+        assert_eq!(same_key, current);
+    }
+
+    #[actix_web::test]
+    async fn imports_its_own_exports_unchanged() {
+        let mut router = parameters(vec![AuthMethod::Password("secret".to_string())]);
+        router.poll_interval = Some(30);
+        router.timeouts.rpc = Some(10);
+        router.tags.insert("site".to_string(), "dc \"1\"".to_string());
+        router.groups.insert("core".to_string());
+        router.groups.insert("edge".to_string());
+        let mut switch = parameters(vec![
+            AuthMethod::PrivateKey { key: PrivateKey::File(PathBuf::from("/keys/id_ed25519")), passphrase: None },
+            AuthMethod::Agent,
+        ]);
+        switch.port = 2830;
+        let hosts = HashMap::from([("192.0.2.1".to_string(), router), ("192.0.2.2".to_string(), switch)]);
+
+        let directory = env::temp_dir().join(format!("hosts_bulk_{}", std::process::id()));
+        let host_store: Arc<HostStore> = Arc::new(FileStore::new(directory.join("hosts.json"), FileFormat::Json));
+        // The documents are only imported in dry runs, so nothing is written to the stores.
+        let profile_store = FileStore::new(directory.join("profiles.json"), FileFormat::Json);
+        let profiles = CredentialProfiles::load(Box::new(profile_store)).unwrap();
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(Arc::new(Mutex::new(hosts))))
+                .app_data(web::Data::from(host_store))
+                .app_data(web::Data::new(profiles))
+                .service(export_hosts)
+                .service(import_hosts),
+        )
+        .await;
+
+        for format in ["csv", "yaml", "ansible"] {
+            let request = TestRequest::get().uri(&format!("/hosts/export?format={}", format)).to_request();
+            let exported = call_and_read_body(&app, request).await;
+            let request = TestRequest::post()
+                .uri(&format!("/hosts/import?format={}&dry_run=true", format))
+                .set_payload(exported)
+                .to_request();
+            let report: Value = call_and_read_body_json(&app, request).await;
+            assert_eq!(report["unchanged"], 2, "{}: {}", format, report);
+        }
+    }
+}
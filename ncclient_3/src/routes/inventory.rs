@@ -0,0 +1,74 @@
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+
+use crate::collector::InventoryItem;
+use crate::errors::ApiError;
+use crate::utils::csv_field;
+use crate::AppState;
+
+/// Columns of the CSV inventory, in the order of the `InventoryItem` fields.
+const CSV_HEADER: &str = "host,component,serial_no,vendor,vendor_part,vendor_rev,last_seen";
+
+/// Struct representing the query parameters of the inventory endpoint.
+#[derive(Debug, Clone, Deserialize)]
+struct InventoryQuery {
+    /// `json` (default) or `csv`.
+    format: Option<String>,
+}
+
+/// Formats the inventory as CSV, with a header line.
+fn to_csv(items: &[InventoryItem]) -> String {
+    let mut csv = format!("{}\n", CSV_HEADER);
+    for item in items {
+        let fields = [
+            Some(item.host.as_str()),
+            Some(item.component.as_str()),
+            item.serial_no.as_deref(),
+            item.vendor.as_deref(),
+            item.vendor_part.as_deref(),
+            item.vendor_rev.as_deref(),
+        ];
+        for field in fields {
+            csv.push_str(&csv_field(field.unwrap_or_default()));
+            csv.push(',');
+        }
+        csv.push_str(&format!("{}\n", item.last_seen));
+    }
+    csv
+}
+
+/// HTTP GET endpoint to retrieve the transceivers of every host found in their last successful collection.
+/// 
+/// # Arguments
+/// 
+/// * `query` - A `web::Query<InventoryQuery>` selecting the format of the response.
+/// * `state` - A `web::Data<AppState>` representing the application state.
+/// 
+/// # Returns
+/// 
+/// A `Result` containing the inventory sorted by host and component, as JSON or CSV,
+/// or an `ApiError` if the format is unknown.
+#[get("/inventory")]
+pub async fn inventory(
+    query: web::Query<InventoryQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let mut items: Vec<InventoryItem> = state
+        .inventory
+        .lock()
+        .unwrap()
+        .values()
+        .flatten()
+        .cloned()
+        .collect();
+    items.sort_by(|a, b| (&a.host, &a.component).cmp(&(&b.host, &b.component)));
+
+    match query.format.as_deref() {
+        None | Some("json") => Ok(HttpResponse::Ok().json(items)),
+        Some("csv") => Ok(HttpResponse::Ok()
+            .content_type("text/csv; charset=utf-8")
+            .insert_header(("Content-Disposition", "attachment; filename=\"inventory.csv\""))
+            .body(to_csv(&items))),
+        Some(other) => Err(ApiError::BadRequest(format!("unknown format {:?}, expected json or csv", other))),
+    }
+}
@@ -0,0 +1,123 @@
+use actix_web::{get, post, web, HttpResponse, Responder};
+use serde::Deserialize;
+use std::io;
+
+use crate::errors::ApiError;
+use crate::known_hosts::KnownHosts;
+use crate::session::SessionPool;
+
+/// Struct representing the request body for approving or revoking a host key.
+#[derive(Debug, Clone, Deserialize)]
+struct HostKeyRequest {
+    /// The fingerprint in OpenSSH format, e.g. `SHA256:...`.
+    fingerprint: String,
+    /// The key type, required to approve a fingerprint that is not pending.
+    key_type: Option<String>,
+}
+
+/// HTTP GET endpoint to list the host keys known for every host.
+/// 
+/// # Arguments
+/// 
+/// * `known_hosts` - A `web::Data<KnownHosts>` representing the known hosts store.
+/// 
+/// # Returns
+/// 
+/// An `impl Responder` containing the trusted and pending keys per host.
+#[get("/known_hosts")]
+pub async fn list_known_hosts(known_hosts: web::Data<KnownHosts>) -> impl Responder {
+    web::Json(known_hosts.list())
+}
+
+/// HTTP GET endpoint to retrieve the host keys known for a host.
+/// 
+/// # Arguments
+/// 
+/// * `host` - A `web::Path<String>` representing the host.
+/// * `known_hosts` - A `web::Data<KnownHosts>` representing the known hosts store.
+/// 
+/// # Returns
+/// 
+/// A `Result` containing the trusted and pending keys, or an `ApiError` if no key is known for the host.
+#[get("/known_hosts/{host}")]
+pub async fn get_known_host(
+    host: web::Path<String>,
+    known_hosts: web::Data<KnownHosts>,
+) -> Result<HttpResponse, ApiError> {
+    let known_host = known_hosts
+        .get(&host)
+        .ok_or_else(|| ApiError::NotFound(format!("no host keys known for {}", host)))?;
+    Ok(HttpResponse::Ok().json(known_host))
+}
+
+/// HTTP POST endpoint to trust a host key fingerprint.
+/// 
+/// # Arguments
+/// 
+/// * `host` - A `web::Path<String>` representing the host.
+/// * `known_hosts` - A `web::Data<KnownHosts>` representing the known hosts store.
+/// * `request` - A `web::Json<HostKeyRequest>` containing the fingerprint to trust.
+/// 
+/// # Returns
+/// 
+/// A `Result` containing the updated keys of the host, or an `ApiError` if the key type is
+/// missing or the store cannot be written.
+#[post("/known_hosts/{host}/approve")]
+pub async fn approve_host_key(
+    host: web::Path<String>,
+    known_hosts: web::Data<KnownHosts>,
+    request: web::Json<HostKeyRequest>,
+) -> Result<HttpResponse, ApiError> {
+    // The store is written on the blocking thread pool.
+    let known_host = web::block(move || {
+        known_hosts
+            .approve(&host, &request.fingerprint, request.key_type.as_deref())
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::InvalidInput => ApiError::BadRequest(e.to_string()),
+                _ => ApiError::Internal(format!("failed to save the host keys of {}: {}", host, e)),
+            })
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(known_host))
+}
+
+/// HTTP POST endpoint to stop trusting a host key fingerprint.
+/// 
+/// Pooled sessions to the host are dropped, so the next request verifies the key again.
+/// 
+/// # Arguments
+/// 
+/// * `host` - A `web::Path<String>` representing the host.
+/// * `known_hosts` - A `web::Data<KnownHosts>` representing the known hosts store.
+/// * `session_pool` - A `web::Data<SessionPool>` holding the persistent NETCONF sessions.
+/// * `request` - A `web::Json<HostKeyRequest>` containing the fingerprint to revoke.
+/// 
+/// # Returns
+/// 
+/// A `Result` containing the updated keys of the host, or an `ApiError` if the fingerprint is
+/// unknown or the store cannot be written.
+#[post("/known_hosts/{host}/revoke")]
+pub async fn revoke_host_key(
+    host: web::Path<String>,
+    known_hosts: web::Data<KnownHosts>,
+    session_pool: web::Data<SessionPool>,
+    request: web::Json<HostKeyRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let host = host.into_inner();
+    // The store is written on the blocking thread pool.
+    let revoked = {
+        let host = host.clone();
+        web::block(move || {
+            known_hosts
+                .revoke(&host, &request.fingerprint)
+                .map_err(|e| ApiError::Internal(format!("failed to save the host keys of {}: {}", host, e)))?
+                .ok_or_else(|| {
+                    ApiError::NotFound(format!("fingerprint {} is not known for {}", request.fingerprint, host))
+                })
+        })
+        .await?
+    };
+    let known_host = revoked?;
+    session_pool.forget(&host);
+    Ok(HttpResponse::Ok().json(known_host))
+}
@@ -1,3 +1,14 @@
 pub mod get_json;
+pub mod get_data;
 pub mod add_host;
-pub mod get_hosts;
\ No newline at end of file
+pub mod collect;
+pub mod get_hosts;
+pub mod hosts;
+pub mod hosts_bulk;
+pub mod inventory;
+pub mod known_hosts;
+pub mod out_of_limits;
+pub mod poll_status;
+pub mod probe;
+pub mod profiles;
+pub mod rpc;
\ No newline at end of file
@@ -0,0 +1,51 @@
+use actix_web::{get, web, HttpResponse};
+use serde::Deserialize;
+
+use crate::errors::ApiError;
+use crate::thresholds::{ChannelStatus, Status};
+use crate::AppState;
+
+/// Struct representing the query parameters of the out of limits endpoint.
+#[derive(Debug, Clone, Deserialize)]
+struct OutOfLimitsQuery {
+    /// `warning` (default) to list warnings and alarms, or `alarm` to only list alarms.
+    status: Option<String>,
+}
+
+/// HTTP GET endpoint to list the channels outside the thresholds of their transceiver.
+/// 
+/// # Arguments
+/// 
+/// * `query` - A `web::Query<OutOfLimitsQuery>` selecting the minimum status listed.
+/// * `state` - A `web::Data<AppState>` representing the application state.
+/// 
+/// # Returns
+/// 
+/// A `Result` containing the channels found in the last successful collection of every host,
+/// sorted by host, component, channel and quantity, or an `ApiError` if the status is unknown.
+#[get("/out_of_limits")]
+pub async fn out_of_limits(
+    query: web::Query<OutOfLimitsQuery>,
+    state: web::Data<AppState>,
+) -> Result<HttpResponse, ApiError> {
+    let minimum = match query.status.as_deref() {
+        None | Some("warning") => Status::Warning,
+        Some("alarm") => Status::Alarm,
+        Some(other) => {
+            return Err(ApiError::BadRequest(format!("unknown status {:?}, expected warning or alarm", other)))
+        }
+    };
+    let mut channels: Vec<ChannelStatus> = state
+        .channel_status
+        .lock()
+        .unwrap()
+        .values()
+        .flatten()
+        .filter(|channel| channel.status >= minimum)
+        .cloned()
+        .collect();
+    channels.sort_by(|a, b| {
+        (&a.host, &a.component, &a.channel, a.quantity).cmp(&(&b.host, &b.component, &b.channel, b.quantity))
+    });
+    Ok(HttpResponse::Ok().json(channels))
+}
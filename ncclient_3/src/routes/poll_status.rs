@@ -0,0 +1,18 @@
+use actix_web::{get, web, Responder};
+
+use crate::AppState;
+
+/// HTTP GET endpoint to retrieve the outcome of the last collections of every host.
+/// 
+/// # Arguments
+/// 
+/// * `state` - A `web::Data<AppState>` representing the application state.
+/// 
+/// # Returns
+/// 
+/// An `impl Responder` containing the last success and error timestamps per host.
+#[get("/poll_status")]
+pub async fn poll_status(state: web::Data<AppState>) -> impl Responder {
+    let poll_status = state.poll_status.lock().unwrap().clone();
+    web::Json(poll_status)
+}
@@ -0,0 +1,127 @@
+use actix_web::{get, web, HttpResponse};
+use prometheus::{Encoder, Registry, TextEncoder};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::collector::collect;
+use crate::errors::ApiError;
+use crate::metric_config::MetricsConfig;
+use crate::opt_utils::register_init_metric;
+use crate::session::SessionPool;
+use crate::HostParameters;
+
+/// Struct representing the query parameters of the probe endpoint.
+#[derive(Debug, Clone, Deserialize)]
+struct ProbeQuery {
+    /// The registered host to collect.
+    target: String,
+    /// The module of the metric definition file selecting the metrics, or every metric if absent.
+    module: Option<String>,
+}
+
+/// HTTP GET endpoint to collect a single host and return its metrics, in the style of the
+/// Prometheus multi-target exporters.
+/// 
+/// The metrics are registered on a registry created for the request, so the response only
+/// holds the metrics of the target. The global metrics, poll status and inventory are not
+/// updated. A failed collection is reported by `netconf_up`.
+/// 
+/// # Arguments
+/// 
+/// * `query` - A `web::Query<ProbeQuery>` containing the target and module.
+/// * `metrics_config` - A `web::Data<MetricsConfig>` holding the metric definitions.
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+/// * `session_pool` - A `web::Data<SessionPool>` holding the persistent NETCONF sessions.
+/// 
+/// # Returns
+/// 
+/// A `Result` containing the metrics in the Prometheus text format, or an `ApiError` if the
+/// target or module is unknown.
+#[get("/probe")]
+pub async fn probe(
+    query: web::Query<ProbeQuery>,
+    metrics_config: web::Data<MetricsConfig>,
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+    session_pool: web::Data<SessionPool>,
+) -> Result<HttpResponse, ApiError> {
+    let ProbeQuery { target, module } = query.into_inner();
+    // Retrieve the host parameters, releasing the lock before any network call.
+    let host_parameters = host_dictionary
+        .lock()
+        .unwrap()
+        .get(&target)
+        .cloned()
+        .ok_or_else(|| ApiError::UnknownHost(target.clone()))?;
+    let config = metrics_config
+        .module(module.as_deref())
+        .ok_or_else(|| ApiError::BadRequest(format!("unknown module {}", module.unwrap_or_default())))?;
+
+    // Register the metrics of the module on a registry used by this request only.
+    let registry = Registry::new();
+    let state = register_init_metric(registry.clone(), &config).map_err(|e| ApiError::Internal(e.to_string()))?;
+
+    // Collect the target. The outcome is exported by `netconf_up` and the reason of a
+    // failure by `netconf_scrape_errors_total`.
+    let _ = collect(&target, host_parameters, &host_dictionary, &session_pool, &state).await;
+
+    // Encode the metrics in the Prometheus text format.
+    let mut body = vec![];
+    let encoder = TextEncoder::new();
+    encoder
+        .encode(&registry.gather(), &mut body)
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+    Ok(HttpResponse::Ok().content_type(encoder.format_type()).body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prometheus::{GaugeVec, Opts};
+
+    const METRICS: &str = "
+modules:
+  optics: [input_power_*]
+metrics:
+  - name: input_power_avg
+    help: Input Power Average
+    path: /rpc-reply/data/input-power/avg
+  - name: input_power_instant
+    help: Input Power Instant
+    path: /rpc-reply/data/input-power/instant
+  - name: transceiver_module_temperature_avg
+    help: Module Temperature Average
+    path: /rpc-reply/data/temperature/avg
+";
+
+    /// Returns whether a metric name is already registered.
+    fn is_registered(registry: &Registry, name: &str) -> bool {
+        let gauge = GaugeVec::new(Opts::new(name, "probe"), &["host"]).unwrap();
+        matches!(registry.register(Box::new(gauge)), Err(prometheus::Error::AlreadyReg))
+    }
+
+    #[test]
+    fn registers_only_the_metrics_of_the_module() {
+        let config = MetricsConfig::parse(METRICS).unwrap();
+        let registry = Registry::new();
+        let state = register_init_metric(registry.clone(), &config.module(Some("optics")).unwrap()).unwrap();
+
+        let names: Vec<&str> = state.metrics.iter().map(|m| m.definition.name.as_str()).collect();
+        assert_eq!(names, ["input_power_avg", "input_power_instant"]);
+        assert!(is_registered(&registry, "input_power_avg"));
+        assert!(is_registered(&registry, "input_power_instant"));
+        assert!(!is_registered(&registry, "transceiver_module_temperature_avg"));
+        // The health metrics are part of every module.
+        assert!(is_registered(&registry, "netconf_up"));
+    }
+
+    #[test]
+    fn registers_every_metric_without_module() {
+        let config = MetricsConfig::parse(METRICS).unwrap();
+        let registry = Registry::new();
+        let state = register_init_metric(registry.clone(), &config.module(None).unwrap()).unwrap();
+        assert_eq!(state.metrics.len(), 3);
+        assert!(is_registered(&registry, "transceiver_module_temperature_avg"));
+        assert!(config.module(Some("unknown")).is_none());
+    }
+}
@@ -0,0 +1,215 @@
+use actix_web::{delete, get, put, web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::credentials::{CredentialProfile, CredentialProfiles};
+use crate::errors::ApiError;
+use crate::routes::hosts::{private_key, Credentials};
+use crate::{AuthMethod, HostParameters};
+
+/// Settings of a credential profile, as given to `PUT /credential_profiles/{name}`.
+///
+/// At least one authentication method must be given. They are tried in the order
+/// private key, ssh-agent, password.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProfileSettings {
+    /// The username, replacing the one of the hosts if set.
+    user: Option<String>,
+    /// Password of the user, tried after the private key and the ssh-agent.
+    password: Option<String>,
+    /// Inline PEM encoded private key.
+    private_key: Option<String>,
+    /// Path to a PEM encoded private key file.
+    private_key_path: Option<PathBuf>,
+    /// Passphrase of the private key.
+    passphrase: Option<String>,
+    /// Whether to try the identities of the local ssh-agent.
+    #[serde(default)]
+    use_agent: bool,
+}
+
+/// A credential profile as returned by the API, without its secrets.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileSummary {
+    name: String,
+    user: Option<String>,
+    /// The kinds of the authentication methods, in the order they are tried.
+    auth_methods: Vec<&'static str>,
+    /// The hosts referencing the profile, sorted by name.
+    hosts: Vec<String>,
+}
+
+impl ProfileSummary {
+    /// Builds the summary of a profile and finds the hosts referencing it.
+    fn new(name: &str, profile: &CredentialProfile, host_dictionary: &HashMap<String, HostParameters>) -> Self {
+        let mut hosts: Vec<String> = host_dictionary
+            .iter()
+            .filter(|(_, parameters)| parameters.credential_profile.as_deref() == Some(name))
+            .map(|(host, _)| host.clone())
+            .collect();
+        hosts.sort();
+        ProfileSummary {
+            name: name.to_string(),
+            user: profile.user.clone(),
+            auth_methods: profile
+                .auth_methods
+                .iter()
+                .map(|method| match method {
+                    AuthMethod::PrivateKey { .. } => "private_key",
+                    AuthMethod::Agent => "agent",
+                    AuthMethod::Password(_) => "password",
+                })
+                .collect(),
+            hosts,
+        }
+    }
+}
+
+/// HTTP GET endpoint to list the credential profiles.
+///
+/// # Arguments
+///
+/// * `profiles` - A `web::Data<CredentialProfiles>` holding the credential profiles.
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+///
+/// # Returns
+///
+/// An `HttpResponse` containing the profiles sorted by name, without passwords, keys or passphrases.
+#[get("/credential_profiles")]
+pub async fn list_profiles(
+    profiles: web::Data<CredentialProfiles>,
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+) -> HttpResponse {
+    let host_dictionary = host_dictionary.lock().unwrap();
+    let mut summaries: Vec<ProfileSummary> = profiles
+        .list()
+        .iter()
+        .map(|(name, profile)| ProfileSummary::new(name, profile, &host_dictionary))
+        .collect();
+    summaries.sort_by(|a, b| a.name.cmp(&b.name));
+    HttpResponse::Ok().json(summaries)
+}
+
+/// HTTP GET endpoint to retrieve a credential profile.
+///
+/// # Arguments
+///
+/// * `name` - A `web::Path<String>` representing the profile name.
+/// * `profiles` - A `web::Data<CredentialProfiles>` holding the credential profiles.
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+///
+/// # Returns
+///
+/// A `Result` containing the profile without its secrets, or an `ApiError` if the profile is unknown.
+#[get("/credential_profiles/{name}")]
+pub async fn get_profile(
+    name: web::Path<String>,
+    profiles: web::Data<CredentialProfiles>,
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+) -> Result<HttpResponse, ApiError> {
+    let profile = profiles
+        .get(&name)
+        .ok_or_else(|| ApiError::NotFound(format!("unknown credential profile {}", name)))?;
+    let host_dictionary = host_dictionary.lock().unwrap();
+    Ok(HttpResponse::Ok().json(ProfileSummary::new(&name, &profile, &host_dictionary)))
+}
+
+/// HTTP PUT endpoint to create or replace a credential profile.
+///
+/// The hosts referencing the profile use the new credentials from their next session.
+///
+/// # Arguments
+///
+/// * `name` - A `web::Path<String>` representing the profile name.
+/// * `profiles` - A `web::Data<CredentialProfiles>` holding the credential profiles.
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+/// * `request` - A `web::Json<ProfileSettings>` containing the credentials.
+///
+/// # Returns
+///
+/// A `Result` containing the stored profile without its secrets, or an `ApiError` if the
+/// request is invalid or the profile cannot be stored.
+#[put("/credential_profiles/{name}")]
+pub async fn put_profile(
+    name: web::Path<String>,
+    profiles: web::Data<CredentialProfiles>,
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+    request: web::Json<ProfileSettings>,
+) -> Result<HttpResponse, ApiError> {
+    let settings = request.into_inner();
+    let credentials = Credentials {
+        private_key: private_key(settings.private_key, settings.private_key_path)?,
+        passphrase: settings.passphrase,
+        use_agent: settings.use_agent,
+        password: settings.password,
+    };
+    let auth_methods = credentials.into_methods();
+    if auth_methods.is_empty() {
+        return Err(ApiError::BadRequest(
+            "at least one of password, private_key, private_key_path or use_agent is required".to_string(),
+        ));
+    }
+    let profile = CredentialProfile { user: settings.user, auth_methods };
+    // The store is written on the blocking thread pool.
+    let summary = web::block(move || {
+        profiles.put(&name, profile.clone()).map_err(|e| match e.kind() {
+            // Secrets without a master key, which the client may replace.
+            io::ErrorKind::PermissionDenied => ApiError::BadRequest(e.to_string()),
+            _ => ApiError::Internal(format!("failed to store credential profile {}: {}", name, e)),
+        })?;
+        let host_dictionary = host_dictionary.lock().unwrap();
+        Ok::<_, ApiError>(ProfileSummary::new(&name, &profile, &host_dictionary))
+    })
+    .await??;
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+/// HTTP DELETE endpoint to remove a credential profile no host references.
+///
+/// # Arguments
+///
+/// * `name` - A `web::Path<String>` representing the profile name.
+/// * `profiles` - A `web::Data<CredentialProfiles>` holding the credential profiles.
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+///
+/// # Returns
+///
+/// A `Result` containing an empty response with status `204`, or an `ApiError` if the profile
+/// is unknown, still referenced or cannot be removed.
+#[delete("/credential_profiles/{name}")]
+pub async fn delete_profile(
+    name: web::Path<String>,
+    profiles: web::Data<CredentialProfiles>,
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+) -> Result<HttpResponse, ApiError> {
+    // Hold the dictionary so no host starts referencing the profile while it is removed.
+    // The store is written on the blocking thread pool.
+    web::block(move || {
+        let host_dictionary = host_dictionary.lock().unwrap();
+        let mut hosts: Vec<&String> = host_dictionary
+            .iter()
+            .filter(|(_, parameters)| parameters.credential_profile.as_deref() == Some(name.as_str()))
+            .map(|(host, _)| host)
+            .collect();
+        if !hosts.is_empty() {
+            hosts.sort();
+            return Err(ApiError::Conflict(format!(
+                "credential profile {} is used by {}",
+                name,
+                hosts.iter().map(|host| host.as_str()).collect::<Vec<_>>().join(", ")
+            )));
+        }
+        let existed = profiles
+            .delete(&name)
+            .map_err(|e| ApiError::Internal(format!("failed to remove credential profile {}: {}", name, e)))?;
+        if !existed {
+            return Err(ApiError::NotFound(format!("unknown credential profile {}", name)));
+        }
+        Ok(())
+    })
+    .await??;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,185 @@
+use actix_web::{post, web, HttpMessage, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::errors::ApiError;
+use crate::session::SessionPool;
+use crate::netconf::holds_end_of_message;
+use crate::utils::{to_json, to_xml, top_level_elements};
+use crate::HostParameters;
+
+/// Struct representing the query parameters of the RPC endpoint.
+#[derive(Debug, Clone, Deserialize)]
+struct RpcQuery {
+    /// `json` (default) or `xml`, the format of the reply.
+    format: Option<String>,
+}
+
+/// Checks the format requested for a reply, `json` if absent.
+///
+/// # Returns
+///
+/// A `Result` containing `json` or `xml`, or an `ApiError` if the format is unknown.
+pub fn reply_format(format: Option<&str>) -> Result<&str, ApiError> {
+    match format.unwrap_or("json") {
+        format @ ("json" | "xml") => Ok(format),
+        other => Err(ApiError::BadRequest(format!("unknown format {:?}, expected json or xml", other))),
+    }
+}
+
+/// Returns an `<rpc-reply>` as raw XML, or converted by `to_json`.
+///
+/// # Arguments
+///
+/// * `reply` - The reply of the device.
+/// * `format` - `json` or `xml`, as returned by `reply_format`.
+///
+/// # Returns
+///
+/// A `Result` containing the response, or an `ApiError` if the reply cannot be parsed.
+pub fn reply_response(reply: String, format: &str) -> Result<HttpResponse, ApiError> {
+    if format == "xml" {
+        return Ok(HttpResponse::Ok().content_type("application/xml").body(reply));
+    }
+    Ok(HttpResponse::Ok().json(to_json(&reply)?))
+}
+
+/// Reads the NETCONF operation of a request, given as XML or as JSON in the layout of `to_json`.
+///
+/// # Arguments
+///
+/// * `request` - The HTTP request, whose content type selects the format of the body.
+/// * `body` - The body of the request.
+///
+/// # Returns
+///
+/// A `Result` containing the XML of the operation, or an `ApiError` if it is invalid, is
+/// not a single operation, or would be rejected by the session.
+fn read_operation(request: &HttpRequest, body: &[u8]) -> Result<String, ApiError> {
+    let body = std::str::from_utf8(body).map_err(|e| ApiError::BadRequest(format!("invalid UTF-8 body: {}", e)))?;
+    let operation = if request.content_type().ends_with("json") {
+        let json: Value =
+            serde_json::from_str(body).map_err(|e| ApiError::BadRequest(format!("invalid JSON body: {}", e)))?;
+        to_xml(&json).map_err(ApiError::BadRequest)?
+    } else {
+        body.to_string()
+    };
+
+    // Check the operation here, a malformed one would leave the session waiting for its end,
+    // and a second one or an end-of-message marker would be run outside of these checks.
+    if holds_end_of_message(&operation) {
+        return Err(ApiError::BadRequest(
+            "the operation holds the ]]>]]> end-of-message marker".to_string(),
+        ));
+    }
+    let elements = top_level_elements(&operation).map_err(ApiError::BadRequest)?;
+    match elements.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => Err(ApiError::BadRequest("the body holds no operation".to_string())),
+        ["rpc"] => Err(ApiError::BadRequest(
+            "send the operation without the <rpc> element, which is added with its message-id".to_string(),
+        )),
+        ["close-session"] => Err(ApiError::BadRequest(
+            "sessions are managed by the service and cannot be closed".to_string(),
+        )),
+        [_] => Ok(operation),
+        _ => Err(ApiError::BadRequest(format!(
+            "the body holds {} operations, send one per request",
+            elements.len()
+        ))),
+    }
+}
+
+/// HTTP POST endpoint to send an arbitrary NETCONF operation to a host.
+///
+/// The body is the operation, e.g. `<get-config><source><running/></source></get-config>`,
+/// as XML or as JSON (`Content-Type: application/json`) converted by `to_xml`. It is
+/// wrapped in an `<rpc>` element and sent over a pooled session.
+///
+/// # Arguments
+///
+/// * `host` - A `web::Path<String>` representing the host.
+/// * `query` - A `web::Query<RpcQuery>` selecting the format of the reply.
+/// * `request` - The HTTP request.
+/// * `body` - A `web::Bytes` holding the operation.
+/// * `host_dictionary` - A `web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>` representing the host dictionary.
+/// * `session_pool` - A `web::Data<SessionPool>` holding the persistent NETCONF sessions.
+///
+/// # Returns
+///
+/// A `Result` containing the `<rpc-reply>` as XML or converted by `to_json`, or an
+/// `ApiError` if the host is unknown, the operation is invalid, or the device fails,
+/// times out or answers with an `<rpc-error>`.
+#[post("/hosts/{host}/rpc")]
+pub async fn rpc(
+    host: web::Path<String>,
+    query: web::Query<RpcQuery>,
+    request: HttpRequest,
+    body: web::Bytes,
+    host_dictionary: web::Data<Arc<Mutex<HashMap<String, HostParameters>>>>,
+    session_pool: web::Data<SessionPool>,
+) -> Result<HttpResponse, ApiError> {
+    let host = host.into_inner();
+    let format = reply_format(query.format.as_deref())?;
+    let operation = read_operation(&request, &body)?;
+    // Retrieve the host parameters, releasing the lock before any network call.
+    let parameters = host_dictionary
+        .lock()
+        .unwrap()
+        .get(&host)
+        .cloned()
+        .ok_or_else(|| ApiError::UnknownHost(host.clone()))?;
+
+    // The exchange is blocking, so it runs on the blocking thread pool instead of the worker.
+    let reply = web::block(move || -> Result<String, ApiError> {
+        Ok(session_pool.rpc(&host, &parameters, &operation)?)
+    })
+    .await??;
+    reply_response(reply, format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn operation(content_type: &str, body: &str) -> Result<String, ApiError> {
+        let request = TestRequest::default()
+            .insert_header(("content-type", content_type))
+            .to_http_request();
+        read_operation(&request, body.as_bytes())
+    }
+
+    #[test]
+    fn reads_xml_and_json_operations() {
+        let get_config = "<get-config><source><running/></source></get-config>";
+        assert_eq!(operation("application/xml", get_config).unwrap(), get_config);
+        let json = r#"{"get-config": {"source": {"running": null}}}"#;
+        assert_eq!(operation("application/json", json).unwrap(), get_config);
+    }
+
+    #[test]
+    fn rejects_a_second_operation() {
+        for body in [
+            "<get/><close-session/>",
+            "<get/>\n<kill-session><session-id>1</session-id></kill-session>",
+            "<get/>]]>]]><close-session/>",
+            "<get><filter>]]>]]></filter></get>",
+            "<get/>text",
+            "",
+            "<rpc><get/></rpc>",
+            "<close-session/>",
+            "<get>",
+            "<?xml version=\"1.0\"?><get/>",
+        ] {
+            assert!(
+                matches!(operation("application/xml", body), Err(ApiError::BadRequest(_))),
+                "{:?} was accepted",
+                body
+            );
+        }
+        let json = r#"{"get": null, "close-session": null}"#;
+        assert!(matches!(operation("application/json", json), Err(ApiError::BadRequest(_))));
+    }
+}
@@ -0,0 +1,352 @@
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::collector::series_tags;
+use crate::credentials::CredentialProfiles;
+use crate::known_hosts::KnownHosts;
+use crate::netconf::{NetconfSession, RpcErrors, ServerHello, TimeoutError, Timeouts};
+use crate::tags::with_tags;
+use crate::utils::top_level_elements;
+use crate::{AppState, HostParameters};
+
+/// Default limit of concurrent sessions per host, overridden by the
+/// `NETCONF_MAX_SESSIONS_PER_HOST` environment variable.
+const DEFAULT_MAX_SESSIONS_PER_HOST: usize = 2;
+
+/// Sessions kept for a single host.
+struct HostSessions {
+    /// The parameters the sessions were opened with.
+    parameters: HostParameters,
+    /// Open sessions waiting to be reused.
+    idle: Vec<NetconfSession>,
+    /// Number of sessions checked out, including those being connected and those of older
+    /// generations.
+    active: usize,
+    /// Generation of the sessions, changed by `forget`. Sessions of older generations are
+    /// closed when they are returned.
+    generation: u64,
+}
+
+/// Pool of persistent NETCONF sessions, keyed by host.
+///
+/// Sessions are reused across RPCs, checked before being handed out, and
+/// replaced when they drop or when the parameters of the host change.
+pub struct SessionPool {
+    /// Sessions per host name.
+    hosts: Mutex<HashMap<String, HostSessions>>,
+    /// Signalled whenever a session is returned to the pool or discarded.
+    released: Condvar,
+    /// Maximum number of sessions open at the same time to a single host.
+    max_sessions_per_host: usize,
+    /// The store of trusted host keys checked on every new connection.
+    known_hosts: Arc<KnownHosts>,
+    /// The timeouts used for hosts that do not set their own.
+    default_timeouts: Timeouts,
+    /// The credential profiles referenced by the hosts.
+    profiles: Arc<CredentialProfiles>,
+    /// The application state holding the RPC duration and reply size metrics.
+    state: AppState,
+}
+
+impl SessionPool {
+    /// Creates an empty pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_sessions_per_host` - Maximum number of concurrent sessions to a single host.
+    /// * `known_hosts` - The store of trusted host keys.
+    /// * `default_timeouts` - The timeouts used for hosts that do not set their own.
+    /// * `profiles` - The credential profiles referenced by the hosts.
+    /// * `state` - The application state holding the metrics of every RPC.
+    pub fn new(
+        max_sessions_per_host: usize,
+        known_hosts: Arc<KnownHosts>,
+        default_timeouts: Timeouts,
+        profiles: Arc<CredentialProfiles>,
+        state: AppState,
+    ) -> Self {
+        SessionPool {
+            hosts: Mutex::new(HashMap::new()),
+            released: Condvar::new(),
+            max_sessions_per_host: max_sessions_per_host.max(1),
+            known_hosts,
+            default_timeouts,
+            profiles,
+            state,
+        }
+    }
+
+    /// Creates an empty pool using the `NETCONF_MAX_SESSIONS_PER_HOST` environment variable
+    /// and the default timeouts given by `Timeouts::from_env`.
+    pub fn from_env(known_hosts: Arc<KnownHosts>, profiles: Arc<CredentialProfiles>, state: AppState) -> Self {
+        let max_sessions_per_host = env::var("NETCONF_MAX_SESSIONS_PER_HOST")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_SESSIONS_PER_HOST);
+        SessionPool::new(max_sessions_per_host, known_hosts, Timeouts::from_env(), profiles, state)
+    }
+
+    /// Drops the idle sessions of a host.
+    ///
+    /// Sessions checked out at that moment belong to the previous generation, and are
+    /// closed when they are returned. They still count towards the session limit until then.
+    pub fn forget(&self, host: &str) {
+        if let Some(entry) = self.hosts.lock().unwrap().get_mut(host) {
+            entry.idle.clear();
+            entry.generation += 1;
+        }
+    }
+
+    /// Sends a NETCONF operation to a host over a pooled session.
+    ///
+    /// If a reused session fails, it is discarded and the operation is retried on
+    /// another session, reconnecting if needed. Timeouts are never retried, and
+    /// sessions whose RPC was answered with `RpcErrors` stay in the pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The hostname or IP address of the target device.
+    /// * `parameters` - The connection parameters of the host.
+    /// * `operation` - The operation to send inside the `<rpc>` element.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `<rpc-reply>` as a `String` or an `Error` if the exchange fails.
+    pub fn rpc(
+        &self,
+        host: &str,
+        parameters: &HostParameters,
+        operation: &str,
+    ) -> Result<String, Box<dyn Error>> {
+        self.rpc_with(host, parameters, |_| Ok(operation.to_string()))
+    }
+
+    /// Sends a NETCONF operation built from the capabilities of the session it is sent on.
+    ///
+    /// Behaves like `rpc`, calling `build` again whenever another session is used. The
+    /// duration of the operation, retries included, and the size of its reply are recorded
+    /// in `netconf_scrape_duration_seconds` and `netconf_reply_bytes`.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - The hostname or IP address of the target device.
+    /// * `parameters` - The connection parameters of the host.
+    /// * `build` - Builds the operation from the server hello, or returns an error if the
+    ///   server lacks a capability it needs.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `<rpc-reply>` as a `String` or an `Error` if the operation
+    /// cannot be built or the exchange fails.
+    pub fn rpc_with<F>(&self, host: &str, parameters: &HostParameters, build: F) -> Result<String, Box<dyn Error>>
+    where
+        F: Fn(&ServerHello) -> Result<String, Box<dyn Error>>,
+    {
+        // Use the credentials of the profile of the host. Sessions opened with credentials
+        // that were changed since are not reused.
+        let parameters = &self.profiles.resolve(parameters)?;
+        let started = Instant::now();
+        loop {
+            let (mut session, reused, generation) = self.acquire(host, parameters)?;
+            let operation = match build(session.hello()) {
+                Ok(operation) => operation,
+                Err(e) => {
+                    self.release(host, parameters, session, generation);
+                    return Err(e);
+                }
+            };
+            match session.rpc(&operation) {
+                Ok(reply) => {
+                    self.record(host, parameters, generation, &operation, started.elapsed(), Some(reply.len()));
+                    self.release(host, parameters, session, generation);
+                    return Ok(reply);
+                }
+                Err(e) if e.is::<RpcErrors>() => {
+                    self.record(host, parameters, generation, &operation, started.elapsed(), None);
+                    self.release(host, parameters, session, generation);
+                    return Err(e);
+                }
+                Err(e) => {
+                    self.discard(host);
+                    if !reused || e.is::<TimeoutError>() {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records the duration of an operation answered by a host and the size of its reply,
+    /// labelled with the name of the operation and the tags of the host.
+    ///
+    /// Nothing is recorded if the host was forgotten since the session was checked out, so
+    /// that the series of a deleted host are not recreated.
+    fn record(
+        &self,
+        host: &str,
+        parameters: &HostParameters,
+        generation: u64,
+        operation: &str,
+        elapsed: Duration,
+        reply_bytes: Option<usize>,
+    ) {
+        let hosts = self.hosts.lock().unwrap();
+        if hosts.get(host).map(|entry| entry.generation) != Some(generation) {
+            return;
+        }
+        let name = operation_name(operation);
+        let tags = series_tags(&self.state, host, parameters);
+        let metrics = &self.state.poll_metrics;
+        metrics.scrape_duration.with_label_values(&with_tags(&[host, &name], &tags)).set(elapsed.as_secs_f64());
+        if let Some(reply_bytes) = reply_bytes {
+            metrics.reply_bytes.with_label_values(&with_tags(&[host], &tags)).set(reply_bytes as f64);
+        }
+    }
+
+    /// Checks out a session for a host, waiting while the host is at its session limit.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the session, whether it was reused from the pool and its generation.
+    fn acquire(
+        &self,
+        host: &str,
+        parameters: &HostParameters,
+    ) -> Result<(NetconfSession, bool, u64), Box<dyn Error>> {
+        loop {
+            let (idle, generation) = {
+                let mut hosts = self.hosts.lock().unwrap();
+                loop {
+                    let entry = hosts.entry(host.to_string()).or_insert_with(|| HostSessions {
+                        parameters: parameters.clone(),
+                        idle: vec![],
+                        active: 0,
+                        generation: 0,
+                    });
+                    // Sessions opened with outdated connection parameters are not reused.
+                    if !same_connection(&entry.parameters, parameters) {
+                        entry.parameters = parameters.clone();
+                        entry.idle.clear();
+                    }
+                    if let Some(session) = entry.idle.pop() {
+                        entry.active += 1;
+                        break (Some(session), entry.generation);
+                    }
+                    if entry.active < self.max_sessions_per_host {
+                        entry.active += 1;
+                        break (None, entry.generation);
+                    }
+                    hosts = self.released.wait(hosts).unwrap();
+                }
+            };
+
+            match idle {
+                Some(session) if session.is_alive() => return Ok((session, true, generation)),
+                Some(_) => self.discard(host),
+                None => {
+                    return match NetconfSession::connect(host, parameters, &self.known_hosts, &self.default_timeouts) {
+                        Ok(session) => Ok((session, false, generation)),
+                        Err(e) => {
+                            self.discard(host);
+                            Err(e)
+                        }
+                    };
+                }
+            }
+        }
+    }
+
+    /// Returns a healthy session to the pool, or closes it if the host was forgotten since
+    /// it was checked out.
+    fn release(&self, host: &str, parameters: &HostParameters, session: NetconfSession, generation: u64) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let outdated = match hosts.get_mut(host) {
+            Some(entry) => {
+                entry.active -= 1;
+                if entry.generation == generation && same_connection(&entry.parameters, parameters) {
+                    entry.idle.push(session);
+                    None
+                } else {
+                    Some(session)
+                }
+            }
+            None => Some(session),
+        };
+        drop(hosts);
+        self.released.notify_one();
+
+        if let Some(session) = outdated {
+            let _ = session.close();
+        }
+    }
+
+    /// Forgets a checked out session that failed or could not be opened.
+    fn discard(&self, host: &str) {
+        if let Some(entry) = self.hosts.lock().unwrap().get_mut(host) {
+            entry.active -= 1;
+        }
+        self.released.notify_one();
+    }
+}
+
+/// Returns the name of the top-level element of an operation, e.g. `get`.
+fn operation_name(operation: &str) -> String {
+    top_level_elements(operation)
+        .ok()
+        .and_then(|names| names.into_iter().next())
+        .unwrap_or_default()
+}
+
+/// Whether sessions opened with one set of parameters can serve another.
+///
+/// Only the fields used to connect are compared, so changing the tags, groups or polling
+/// interval of a host keeps its sessions.
+fn same_connection(a: &HostParameters, b: &HostParameters) -> bool {
+    a.port == b.port && a.user == b.user && a.auth_methods == b.auth_methods && a.timeouts == b.timeouts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AuthMethod;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    fn parameters() -> HostParameters {
+        HostParameters {
+            port: 830,
+            user: "admin".to_string(),
+            auth_methods: vec![AuthMethod::Password("secret".to_string())],
+            timeouts: Timeouts::default(),
+            poll_interval: None,
+            credential_profile: None,
+            tags: BTreeMap::new(),
+            groups: BTreeSet::new(),
+        }
+    }
+
+    #[test]
+    fn compares_only_the_connection_fields() {
+        let mut metadata = parameters();
+        metadata.poll_interval = Some(10);
+        metadata.tags.insert("site".to_string(), "lab".to_string());
+        metadata.groups.insert("core".to_string());
+        assert!(same_connection(&parameters(), &metadata));
+
+        let mut port = parameters();
+        port.port = 22;
+        assert!(!same_connection(&parameters(), &port));
+        let mut auth = parameters();
+        auth.auth_methods = vec![AuthMethod::Password("other".to_string())];
+        assert!(!same_connection(&parameters(), &auth));
+    }
+
+    #[test]
+    fn names_operations_after_their_element() {
+        assert_eq!(operation_name("\n<get>\n  <filter type=\"subtree\"/>\n</get>\n"), "get");
+        assert_eq!(operation_name("<nc:get-config xmlns:nc=\"urn:x\"><source/></nc:get-config>"), "get-config");
+        assert_eq!(operation_name("<lock"), "");
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::HostParameters;
+
+/// Returns whether a tag key is a valid Prometheus label name, so it can be exported as a label.
+pub fn is_valid_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Checks the tags and groups of a host.
+///
+/// Tag keys must be valid label names, and tag values and group names cannot hold `,`,
+/// which separates the terms of a selector. Group names cannot hold `;` either, which
+/// separates the groups in CSV files.
+///
+/// # Returns
+///
+/// A `Result` that is an error message if a tag or group is invalid.
+pub fn validate(tags: &BTreeMap<String, String>, groups: &BTreeSet<String>) -> Result<(), String> {
+    if let Some(key) = tags.keys().find(|key| !is_valid_key(key)) {
+        return Err(format!(
+            "invalid tag key {:?}, expected letters, digits and underscores not starting with a digit",
+            key
+        ));
+    }
+    if let Some((key, _)) = tags.iter().find(|(_, value)| value.contains(',')) {
+        return Err(format!("the value of tag {} cannot contain ','", key));
+    }
+    if let Some(group) = groups.iter().find(|group| group.is_empty() || group.contains([',', ';'])) {
+        return Err(format!("invalid group {:?}, expected a non-empty name without ',' or ';'", group));
+    }
+    Ok(())
+}
+
+/// Selects hosts by tags and group, as in `/collect?tag=site:mad1,role:edge&group=core`.
+#[derive(Debug, Clone, Default)]
+pub struct Selector {
+    /// Tag keys with the value they must have, or `None` if any value matches.
+    tags: Vec<(String, Option<String>)>,
+    /// The group the hosts must be in.
+    group: Option<String>,
+}
+
+impl Selector {
+    /// Parses a selector.
+    ///
+    /// # Arguments
+    ///
+    /// * `tags` - Comma separated `key:value` terms, or `key` terms matching any value.
+    /// * `group` - The group the hosts must be in.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the selector, or an error message if a term has no key.
+    pub fn parse(tags: Option<&str>, group: Option<&str>) -> Result<Self, String> {
+        let tags = tags
+            .into_iter()
+            .flat_map(|tags| tags.split(','))
+            .map(|term| {
+                let (key, value) = match term.split_once(':') {
+                    Some((key, value)) => (key.trim(), Some(value.trim().to_string())),
+                    None => (term.trim(), None),
+                };
+                if key.is_empty() {
+                    return Err(format!("invalid tag selector {:?}, expected key:value or key", term));
+                }
+                Ok((key.to_string(), value))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Selector { tags, group: group.map(str::to_string) })
+    }
+
+    /// Returns whether the selector has no term, matching every host.
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.group.is_none()
+    }
+
+    /// Returns whether a host has every selected tag and is in the selected group.
+    pub fn matches(&self, parameters: &HostParameters) -> bool {
+        self.tags.iter().all(|(key, value)| match (parameters.tags.get(key), value) {
+            (Some(tag), Some(value)) => tag == value,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }) && self.group.as_ref().is_none_or(|group| parameters.groups.contains(group))
+    }
+}
+
+/// Returns the values of the tags exported as labels, empty for the tags the host does not have.
+pub fn label_values(tag_labels: &[String], parameters: &HostParameters) -> Vec<String> {
+    tag_labels
+        .iter()
+        .map(|key| parameters.tags.get(key).cloned().unwrap_or_default())
+        .collect()
+}
+
+/// Appends the values of the tag labels to the other label values of a series.
+pub fn with_tags<'a>(labels: &[&'a str], tags: &'a [String]) -> Vec<&'a str> {
+    let mut values = labels.to_vec();
+    values.extend(tags.iter().map(|tag| tag.as_str()));
+    values
+}
@@ -0,0 +1,231 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::collector::{as_list, value_to_f64, value_to_label};
+use crate::metric_config::resolve_alternatives;
+
+/// Channel quantities compared with the thresholds. Each is a container of the channel
+/// state, and its limits are the `<quantity>-upper` and `<quantity>-lower` threshold leaves.
+pub const QUANTITIES: [&str; 3] = ["input-power", "output-power", "laser-bias-current"];
+
+/// Location of the severity in a threshold entry, the list key or the state leaf, with
+/// alternatives separated by `|` like the locations of `metrics.yaml`.
+const SEVERITY: &str = "severity | state/severity";
+
+/// Status of a channel quantity compared with the thresholds of its transceiver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// The value is within all thresholds.
+    Ok,
+    /// The value is outside a `MINOR` or `WARNING` threshold.
+    Warning,
+    /// The value is outside a `MAJOR` or `CRITICAL` threshold.
+    Alarm,
+}
+
+impl Status {
+    /// Returns the status of a value outside a threshold of the given severity.
+    ///
+    /// The severity is an `openconfig-alarm-types` identity, with or without its module prefix.
+    fn from_severity(severity: &str) -> Option<Status> {
+        let severity = severity.rsplit(':').next().unwrap_or(severity);
+        match severity {
+            "CRITICAL" | "MAJOR" => Some(Status::Alarm),
+            "MINOR" | "WARNING" => Some(Status::Warning),
+            _ => None,
+        }
+    }
+
+    /// Returns the value exported in the `transceiver_channel_status` gauge.
+    pub fn value(self) -> f64 {
+        match self {
+            Status::Ok => 0.0,
+            Status::Warning => 1.0,
+            Status::Alarm => 2.0,
+        }
+    }
+}
+
+/// Status of a quantity of a physical channel.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelStatus {
+    /// The hostname or IP address of the device.
+    pub host: String,
+    /// The name of the transceiver component.
+    pub component: String,
+    /// The index of the physical channel.
+    pub channel: String,
+    /// The quantity compared, one of `QUANTITIES`.
+    pub quantity: &'static str,
+    /// The instant value of the quantity.
+    pub value: f64,
+    /// The worst status of the value.
+    pub status: Status,
+    /// The lower limit of the threshold crossed, if any.
+    pub lower: Option<f64>,
+    /// The upper limit of the threshold crossed, if any.
+    pub upper: Option<f64>,
+}
+
+/// Limits of a quantity for one severity.
+struct Limits {
+    /// The status of a value outside the limits.
+    status: Status,
+    /// The lower limit.
+    lower: Option<f64>,
+    /// The upper limit.
+    upper: Option<f64>,
+}
+
+impl Limits {
+    /// Returns whether a value is outside the limits.
+    fn crossed(&self, value: f64) -> bool {
+        self.lower.is_some_and(|lower| value < lower) || self.upper.is_some_and(|upper| value > upper)
+    }
+}
+
+/// Compares the channels of every present transceiver with the thresholds of the transceiver.
+///
+/// # Arguments
+///
+/// * `host` - The hostname or IP address of the device.
+/// * `reply` - The reply converted by `utils::to_json`.
+///
+/// # Returns
+///
+/// The status of every quantity of every channel that has an instant value and at least
+/// one threshold.
+pub fn evaluate(host: &str, reply: &Value) -> Vec<ChannelStatus> {
+    let mut statuses = vec![];
+    for component in as_list(reply.pointer("/rpc-reply/data/components/component")) {
+        let name = match component.get("name") {
+            Some(name) => value_to_label(name),
+            None => continue,
+        };
+        let transceiver = match component.get("transceiver") {
+            Some(transceiver) if transceiver.pointer("/state/present").and_then(Value::as_str) == Some("PRESENT") => {
+                transceiver
+            }
+            _ => continue,
+        };
+        let thresholds: Vec<(Status, &Value)> = as_list(transceiver.pointer("/thresholds/threshold"))
+            .into_iter()
+            .filter_map(|threshold| {
+                let status = Status::from_severity(&resolve_alternatives(threshold, SEVERITY)?)?;
+                Some((status, threshold.get("state")?))
+            })
+            .collect();
+        if thresholds.is_empty() {
+            continue;
+        }
+
+        for quantity in QUANTITIES {
+            // Collect the limits of the quantity for every severity.
+            let limits: Vec<Limits> = thresholds
+                .iter()
+                .map(|(status, threshold)| {
+                    let limit = |bound: &str| {
+                        threshold
+                            .get(format!("{}-{}", quantity, bound).as_str())
+                            .and_then(value_to_f64)
+                    };
+                    Limits { status: *status, lower: limit("lower"), upper: limit("upper") }
+                })
+                .filter(|limits| limits.lower.is_some() || limits.upper.is_some())
+                .collect();
+            if limits.is_empty() {
+                continue;
+            }
+
+            let channels = as_list(transceiver.pointer("/physical-channels/channel"));
+            for (position, channel) in channels.into_iter().enumerate() {
+                let value = match channel
+                    .pointer(&format!("/state/{}/instant", quantity))
+                    .and_then(value_to_f64)
+                {
+                    Some(value) => value,
+                    None => continue,
+                };
+                // The index key of the list is used, or the position like in the metric labels.
+                let index = channel
+                    .get("index")
+                    .or_else(|| channel.pointer("/state/index"))
+                    .map(value_to_label)
+                    .unwrap_or_else(|| position.to_string());
+                let crossed = limits
+                    .iter()
+                    .filter(|limits| limits.crossed(value))
+                    .max_by_key(|limits| limits.status);
+                statuses.push(ChannelStatus {
+                    host: host.to_string(),
+                    component: name.clone(),
+                    channel: index,
+                    quantity,
+                    value,
+                    status: crossed.map_or(Status::Ok, |limits| limits.status),
+                    lower: crossed.and_then(|limits| limits.lower),
+                    upper: crossed.and_then(|limits| limits.upper),
+                });
+            }
+        }
+    }
+    statuses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Returns a reply with one present transceiver, one channel and the given thresholds.
+    fn reply(instant: &str, thresholds: Value) -> Value {
+        json!({"rpc-reply": {"data": {"components": {"component": {
+            "name": "xcvr-1",
+            "transceiver": {
+                "state": {"present": "PRESENT"},
+                "physical-channels": {"channel": {"index": "0", "state": {"input-power": {"instant": instant}}}},
+                "thresholds": {"threshold": thresholds},
+            },
+        }}}}})
+    }
+
+    #[test]
+    fn reads_the_severity_from_the_key_or_the_state() {
+        let thresholds = json!([
+            {"severity": "oc-alarm-types:WARNING", "state": {"input-power-lower": "-10.0"}},
+            {"state": {"severity": "oc-alarm-types:CRITICAL", "input-power-lower": "-20.0"}},
+        ]);
+        let statuses = evaluate("r1", &reply("-15.0", thresholds.clone()));
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].status, Status::Warning);
+        assert_eq!(statuses[0].lower, Some(-10.0));
+
+        let statuses = evaluate("r1", &reply("-25.0", thresholds));
+        assert_eq!(statuses[0].status, Status::Alarm);
+        assert_eq!(statuses[0].lower, Some(-20.0));
+    }
+
+    #[test]
+    fn prefers_the_key_over_the_state() {
+        let thresholds = json!({"severity": "MAJOR", "state": {"severity": "MINOR", "input-power-upper": "3.0"}});
+        let statuses = evaluate("r1", &reply("4.0", thresholds));
+        assert_eq!(statuses[0].status, Status::Alarm);
+    }
+
+    #[test]
+    fn ignores_thresholds_without_a_known_severity() {
+        let thresholds = json!({"state": {"input-power-upper": "3.0"}});
+        assert!(evaluate("r1", &reply("4.0", thresholds)).is_empty());
+        let thresholds = json!({"severity": "INFORMATIONAL", "state": {"input-power-upper": "3.0"}});
+        assert!(evaluate("r1", &reply("4.0", thresholds)).is_empty());
+    }
+
+    #[test]
+    fn reports_values_within_the_thresholds_as_ok() {
+        let thresholds = json!({"severity": "MAJOR", "state": {"input-power-lower": "-20.0", "input-power-upper": "3.0"}});
+        let statuses = evaluate("r1", &reply("-2.5", thresholds));
+        assert_eq!(statuses[0].status, Status::Ok);
+        assert_eq!((statuses[0].lower, statuses[0].upper), (None, None));
+    }
+}
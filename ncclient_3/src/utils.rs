@@ -1,31 +1,55 @@
+use quick_xml::errors::IllFormedError;
+use quick_xml::escape::escape;
 use quick_xml::events::Event;
 use quick_xml::Reader;
 use serde_json::{Map, Value};
+use std::fmt;
 
 /// A custom error type for handling parsing errors.
 #[derive(Debug)]
-pub struct Error {}
+pub struct Error {
+    /// The error reported by the XML reader.
+    cause: quick_xml::Error,
+    /// The byte offset in the document where the error was found.
+    position: usize,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid XML at byte {}: {}", self.position, self.cause)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.cause)
+    }
+}
 
 /// Recursively reads an XML reader and converts the content into a JSON `Value`.
 ///
 /// # Arguments
 ///
 /// * `reader` - A mutable reference to a `Reader` instance.
-/// * `depth` - The current depth in the XML tree.
 ///
 /// # Returns
 ///
-/// A `Value` representing the JSON conversion of the XML content.
-fn read(reader: &mut Reader<&[u8]>, depth: u64) -> Value {
+/// A `Result` containing a `Value` representing the JSON conversion of the XML content,
+/// or an `Error` if the XML is malformed.
+fn read(reader: &mut Reader<&[u8]>) -> Result<Value, Error> {
     let mut buf = Vec::new();
     let mut values = Vec::new();
     let mut node = Map::new();
 
     loop {
         match reader.read_event_into(&mut buf) {
-            Ok(Event::Start(ref e)) => {
+            Ok(ref event @ (Event::Start(ref e) | Event::Empty(ref e))) => {
                 if let Ok(name) = String::from_utf8(e.name().into_inner().to_vec()) {
-                    let mut child = read(reader, depth + 1);
+                    // Empty elements like <ok/> have no content to read.
+                    let mut child = match event {
+                        Event::Empty(_) => Value::Null,
+                        _ => read(reader)?,
+                    };
                     let mut attrs = Map::new();
 
                     let _ = e
@@ -94,6 +118,12 @@ fn read(reader: &mut Reader<&[u8]>, depth: u64) -> Value {
             }
             Ok(Event::End(ref _e)) => break,
             Ok(Event::Eof) => break,
+            Err(cause) => {
+                return Err(Error {
+                    cause,
+                    position: reader.error_position(),
+                })
+            }
             _ => (),
         }
     }
@@ -113,14 +143,14 @@ fn read(reader: &mut Reader<&[u8]>, depth: u64) -> Value {
         if has_text {
             node.insert("#text".to_string(), values.remove(index));
         }
-        return serde_json::to_value(&node).expect("Failed to convert node to JSON!");
+        return Ok(Value::Object(node));
     }
 
-    match values.len() {
+    Ok(match values.len() {
         0 => Value::Null,
         1 => values.pop().unwrap(),
         _ => Value::Array(values),
-    }
+    })
 }
 
 /**
@@ -138,5 +168,321 @@ pub fn to_json(xml: &str) -> Result<Value, Error> {
     let mut reader = Reader::from_str(xml);
     reader.config_mut().trim_text(true);
 
-    Ok(read(&mut reader, 0))
+    read(&mut reader)
+}
+
+/// Quotes a CSV field if it contains a separator, a quote or a line break.
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Parses a CSV document, where quoted fields may hold separators, doubled quotes and line breaks.
+///
+/// # Arguments
+///
+/// * `csv` - A string slice containing the CSV data.
+///
+/// # Returns
+///
+/// A `Result` containing the records with the line they start on, skipping blank lines,
+/// or an error message if a quoted field is not terminated.
+pub fn parse_csv(csv: &str) -> Result<Vec<(usize, Vec<String>)>, String> {
+    let mut records = vec![];
+    let mut record = vec![];
+    let mut field = String::new();
+    let mut line = 1;
+    let mut start = 1;
+    let mut quoted = false;
+    let mut chars = csv.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            '\n' if quoted => {
+                line += 1;
+                field.push(c);
+            }
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                // Skip blank lines.
+                if record.len() > 1 || !record[0].is_empty() {
+                    records.push((start, std::mem::take(&mut record)));
+                }
+                record.clear();
+                line += 1;
+                start = line;
+            }
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(format!("unterminated quoted field starting on line {}", start));
+    }
+    record.push(field);
+    if record.len() > 1 || !record[0].is_empty() {
+        records.push((start, record));
+    }
+    Ok(records)
+}
+
+/// Returns whether a JSON key can be used as an XML element or attribute name.
+fn is_xml_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.')
+        && !name.contains(|c: char| c.is_whitespace() || "<>&\"'=/".contains(c))
+}
+
+/// Returns the text of a scalar JSON value, as written in an XML element or attribute.
+fn scalar_text(name: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::Null => Ok(String::new()),
+        Value::String(text) => Ok(text.clone()),
+        Value::Bool(_) | Value::Number(_) => Ok(value.to_string()),
+        _ => Err(format!("{} must be a string, number or boolean", name)),
+    }
+}
+
+/// Writes a JSON value as one XML element per value, repeating the element for lists.
+fn write_element(name: &str, value: &Value, xml: &mut String) -> Result<(), String> {
+    if !is_xml_name(name) {
+        return Err(format!("invalid element name {:?}", name));
+    }
+    let node = match value {
+        Value::Array(entries) => {
+            for entry in entries {
+                if entry.is_array() {
+                    return Err(format!("{} cannot hold nested lists", name));
+                }
+                write_element(name, entry, xml)?;
+            }
+            return Ok(());
+        }
+        Value::Object(node) => node,
+        Value::Null => {
+            xml.push_str(&format!("<{}/>", name));
+            return Ok(());
+        }
+        scalar => {
+            let text = scalar_text(name, scalar)?;
+            xml.push_str(&format!("<{}>{}</{}>", name, escape(&text), name));
+            return Ok(());
+        }
+    };
+
+    let mut content = String::new();
+    xml.push_str(&format!("<{}", name));
+    for (key, child) in node {
+        if let Some(attribute) = key.strip_prefix('@') {
+            if !is_xml_name(attribute) {
+                return Err(format!("invalid attribute name {:?}", attribute));
+            }
+            xml.push_str(&format!(" {}=\"{}\"", attribute, escape(&scalar_text(key, child)?)));
+        } else if key == "#text" || key == "#cdata" {
+            content.push_str(&escape(&scalar_text(key, child)?));
+        } else {
+            write_element(key, child, &mut content)?;
+        }
+    }
+    if content.is_empty() {
+        xml.push_str("/>");
+    } else {
+        xml.push_str(&format!(">{}</{}>", content, name));
+    }
+    Ok(())
+}
+
+/// Converts a JSON `Value` into XML, the reverse of `to_json`.
+///
+/// Every key is an element, lists repeat their element, keys starting with `@` are
+/// attributes, `#text` is the text of the element and `null` is an empty element.
+///
+/// # Arguments
+///
+/// * `json` - The JSON object, whose keys are the top level elements.
+///
+/// # Returns
+///
+/// A `Result` containing the XML, or an error message if the JSON cannot be represented.
+pub fn to_xml(json: &Value) -> Result<String, String> {
+    let elements = json
+        .as_object()
+        .ok_or_else(|| "expected a JSON object whose keys are XML elements".to_string())?;
+    let mut xml = String::new();
+    for (name, value) in elements {
+        write_element(name, value, &mut xml)?;
+    }
+    Ok(xml)
+}
+
+/// Checks that an XML fragment is well formed and returns the local names of its top-level
+/// elements.
+///
+/// # Arguments
+///
+/// * `xml` - A string slice containing the XML data.
+///
+/// # Returns
+///
+/// A `Result` containing the names in document order, or an error message if the XML is
+/// malformed, holds text outside of the elements, or holds an XML declaration, a processing
+/// instruction or a document type, which cannot appear inside an `<rpc>`.
+pub fn top_level_elements(xml: &str) -> Result<Vec<String>, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut elements = vec![];
+    // The names of the elements not closed yet, the reader does not check them at the end.
+    let mut open = vec![];
+    loop {
+        let position = reader.buffer_position();
+        let event = reader.read_event().map_err(|cause| {
+            Error {
+                cause,
+                position: reader.error_position(),
+            }
+            .to_string()
+        })?;
+        if let Event::Decl(_) | Event::PI(_) | Event::DocType(_) = event {
+            return Err(format!(
+                "invalid XML at byte {}: declarations, processing instructions and document types are not allowed",
+                position
+            ));
+        }
+        if let Event::Start(ref e) | Event::Empty(ref e) = event {
+            // The reader only parses the attributes on demand.
+            if let Some(Err(e)) = e.attributes().find(|attribute| attribute.is_err()) {
+                return Err(Error { cause: e.into(), position }.to_string());
+            }
+        }
+        if open.is_empty() {
+            match event {
+                Event::Start(ref e) | Event::Empty(ref e) => {
+                    elements.push(String::from_utf8_lossy(e.local_name().into_inner()).to_string());
+                }
+                Event::Text(ref e) if e.iter().all(u8::is_ascii_whitespace) => (),
+                Event::Text(_) | Event::CData(_) => {
+                    return Err(format!("invalid XML at byte {}: text outside of the elements", position));
+                }
+                _ => (),
+            }
+        }
+        match event {
+            Event::Start(ref e) => open.push(String::from_utf8_lossy(e.name().into_inner()).to_string()),
+            Event::End(_) => {
+                open.pop();
+            }
+            Event::Eof => {
+                return match open.pop() {
+                    Some(name) => Err(Error {
+                        cause: IllFormedError::MissingEndTag(name).into(),
+                        position: xml.len(),
+                    }
+                    .to_string()),
+                    None => Ok(elements),
+                };
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn converts_json_to_xml() {
+        let json = json!({
+            "get-config": {
+                "@xmlns": "urn:ietf:params:xml:ns:netconf:base:1.0",
+                "source": {"running": null},
+                "filter": {"@type": "subtree", "users": {"user": [{"name": "a&b"}, {"name": 1}]}},
+            },
+            "lock": {"target": {"candidate": null}},
+        });
+        assert_eq!(
+            to_xml(&json).unwrap(),
+            "<get-config xmlns=\"urn:ietf:params:xml:ns:netconf:base:1.0\"><source><running/></source>\
+             <filter type=\"subtree\"><users><user><name>a&amp;b</name></user><user><name>1</name></user></users>\
+             </filter></get-config><lock><target><candidate/></target></lock>"
+        );
+        assert_eq!(to_xml(&json!({"a": {"@x": "\"", "#text": "<t>"}})).unwrap(), "<a x=\"&quot;\">&lt;t&gt;</a>");
+        assert_eq!(to_xml(&json!({"a": true, "b": {}})).unwrap(), "<a>true</a><b/>");
+    }
+
+    #[test]
+    fn round_trips_through_to_json() {
+        let xml = "<data><a x=\"1\">text</a><b/><c><d>1</d><d>2</d></c></data>";
+        assert_eq!(to_xml(&to_json(xml).unwrap()).unwrap(), xml);
+    }
+
+    #[test]
+    fn rejects_json_without_xml_form() {
+        for json in [
+            json!(["get"]),
+            json!("get"),
+            json!({"a b": null}),
+            json!({"1a": null}),
+            json!({"a": [[1]]}),
+            json!({"a": {"@x": {"y": 1}}}),
+            json!({"a": {"@x y": 1}}),
+            json!({"a": {"#text": [1]}}),
+        ] {
+            assert!(to_xml(&json).is_err(), "{} was converted", json);
+        }
+    }
+
+    #[test]
+    fn parses_quoted_csv_fields() {
+        let csv = "host,tag.note\r\nr1,\"a, \"\"b\"\"\nc\"\n\n r2 ,\"\"\n";
+        let records = parse_csv(csv).unwrap();
+        assert_eq!(
+            records,
+            [
+                (1, vec!["host".to_string(), "tag.note".to_string()]),
+                (2, vec!["r1".to_string(), "a, \"b\"\nc".to_string()]),
+                (5, vec![" r2 ".to_string(), String::new()]),
+            ]
+        );
+        assert!(parse_csv("host\n\"r1").is_err());
+    }
+
+    #[test]
+    fn quotes_csv_fields_that_need_it() {
+        for value in ["r1", "a,b", "say \"hi\"", "two\nlines", ""] {
+            let record = format!("{},{}", csv_field(value), csv_field("x"));
+            assert_eq!(parse_csv(&record).unwrap(), [(1, vec![value.to_string(), "x".to_string()])]);
+        }
+    }
+
+    #[test]
+    fn lists_top_level_elements() {
+        assert_eq!(top_level_elements("<a><b/></a>").unwrap(), ["a"]);
+        assert_eq!(top_level_elements(" <x:a xmlns:x=\"u\"/>\n<b></b> ").unwrap(), ["a", "b"]);
+        assert_eq!(top_level_elements("<!-- c --><a/>").unwrap(), ["a"]);
+        assert!(top_level_elements("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_fragments() {
+        for xml in ["<a>", "<a><b></a>", "<a></b>", "</a>", "<a/>text", "text<a/>", "<![CDATA[x]]><a/>", "<a x=1/>"] {
+            assert!(top_level_elements(xml).is_err(), "{:?} was accepted", xml);
+        }
+    }
+
+    #[test]
+    fn rejects_declarations_processing_instructions_and_document_types() {
+        for xml in ["<?xml version=\"1.0\"?><get/>", "<?p?><get/>", "<get><?p?></get>", "<!DOCTYPE get><get/>"] {
+            assert!(top_level_elements(xml).is_err(), "{:?} was accepted", xml);
+        }
+    }
 }